@@ -21,13 +21,14 @@ const filterTools = (
   }[],
   criteria: ToolData[],
 ) =>
-  source.filter((sourceItem) =>
-    criteria.some(
+  source.flatMap((sourceItem) => {
+    const data = criteria.find(
       (criteriaItem) =>
         sourceItem.tool.name ===
         `${cleanString(criteriaItem.mcpUrl)}__${criteriaItem.name}`,
-    ),
-  );
+    );
+    return data ? [{ data, tool: sourceItem.tool }] : [];
+  });
 
 const initializeAgent = async (
   mcpUrls: string[],
@@ -73,22 +74,24 @@ const initializeAgent = async (
         baseUrl: `http://localhost:11434`,
         model: config.ollamaModel,
       });
+  // Search results of the tools offered to the model, by their prefixed name, so calls are
+  // logged under the tool name the scheduler knows.
+  const selectedTools = new Map<string, ToolData>();
+
   const toolSelectorMiddleware = createMiddleware({
     name: `ToolSelector`,
     wrapModelCall: async (request, handler) => {
-      const selectedTools = filterTools(
-        tools,
-        await searchTools(registrationId),
-      ).map((tool) => tool.tool);
+      const selected = filterTools(tools, await searchTools(registrationId, config.searchDeadlineMs));
+      for (const { data, tool } of selected) {
+        selectedTools.set(tool.name, data);
+      }
       return handler({
         ...request,
-        tools: selectedTools,
+        tools: selected.map(({ tool }) => tool),
       });
     },
     wrapToolCall: async (request, handler) => {
-      const calledTool = tools.find(
-        (tool) => tool.tool.name === (request.tool.name as string),
-      );
+      const calledTool = selectedTools.get(request.tool.name as string);
 
       const start = performance.now();
       try {
@@ -97,10 +100,11 @@ const initializeAgent = async (
 
         if (calledTool) {
           void logToolCall(
-            calledTool.tool.name,
-            calledTool.url,
+            calledTool.name,
+            calledTool.mcpUrl,
             difference,
             false,
+            registrationId,
           );
         }
 
@@ -111,10 +115,11 @@ const initializeAgent = async (
         const difference = Math.floor(performance.now() - start);
         if (calledTool) {
           void logToolCall(
-            calledTool.tool.name,
-            calledTool.url,
+            calledTool.name,
+            calledTool.mcpUrl,
             difference,
             true,
+            registrationId,
           );
         }
 
@@ -4,6 +4,7 @@ export interface Config {
   openrouterApiKey: string | undefined;
   openrouterModel: string;
   schedulerUrl: string;
+  searchDeadlineMs: number | undefined;
 }
 
 export type LLMProvider = `ollama` | `openrouter`;
@@ -15,6 +16,10 @@ export const config: Config = {
   openrouterModel:
     process.env.OPENROUTER_MODEL ?? `google/gemini-2.0-flash-001`,
   schedulerUrl: process.env.SCHEDULER_URL ?? `http://localhost:4000`,
+  searchDeadlineMs:
+    process.env.SEARCH_DEADLINE_MS ?
+      Number(process.env.SEARCH_DEADLINE_MS)
+    : undefined,
 };
 
 if (config.llmProvider === `openrouter` && !config.openrouterApiKey) {
@@ -22,3 +27,13 @@ if (config.llmProvider === `openrouter` && !config.openrouterApiKey) {
     `LLM_PROVIDER is set to "openrouter" but OPENROUTER_API_KEY is not set.`,
   );
 }
+
+if (
+  config.searchDeadlineMs !== undefined &&
+  !(config.searchDeadlineMs > 0 && Number.isFinite(config.searchDeadlineMs))
+) {
+  console.warn(
+    `SEARCH_DEADLINE_MS must be a positive number of milliseconds; ignoring it.`,
+  );
+  config.searchDeadlineMs = undefined;
+}
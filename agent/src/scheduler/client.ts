@@ -1,9 +1,9 @@
 import { ToolSchemaBase } from '@langchain/core/tools';
 
 import { config } from '@/src/config';
-import { cleanString } from '@/src/utils';
 
 type LogRequest = {
+  batch_id: string;
   is_error: boolean;
   mcp_url: string;
   tool_name: string;
@@ -59,10 +59,14 @@ const registerMcpServers = async (
 
 export const searchTools = async (
   registrationId: string,
+  deadlineMs?: number,
 ): Promise<ToolData[]> => {
   const params = new URLSearchParams({
     batch_id: registrationId,
   });
+  if (deadlineMs !== undefined) {
+    params.set(`deadline_ms`, deadlineMs.toString());
+  }
 
   const response = await fetch(
     `${config.schedulerUrl}/search?${params.toString()}`,
@@ -91,16 +95,16 @@ const logToolCall = async (
   url: string,
   duration: number,
   isError: boolean,
+  registrationId: string,
 ) => {
   const daurl = `${config.schedulerUrl}/log`;
 
-  const realName = name.slice(cleanString(url).length + 2);
-
   const response = await fetch(daurl, {
     body: JSON.stringify({
+      batch_id: registrationId,
       is_error: isError,
       mcp_url: url,
-      tool_name: realName,
+      tool_name: name,
       total_time_ms: duration,
     } satisfies LogRequest),
     headers: {
@@ -110,11 +114,11 @@ const logToolCall = async (
   });
 
   if (!response.ok) {
-    console.log(`Failed to log tool call for ${realName} from ${url}`);
+    console.log(`Failed to log tool call for ${name} from ${url}`);
     return;
   }
 
-  console.log(`Successfully logged tool call for ${realName} from ${url}`);
+  console.log(`Successfully logged tool call for ${name} from ${url}`);
   return (await response.json()) as RegisterResponse;
 };
 
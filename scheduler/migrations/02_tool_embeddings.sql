@@ -0,0 +1,33 @@
+CREATE TABLE IF NOT EXISTS tool_embedding_collections (
+    name TEXT PRIMARY KEY,
+    embedding_model TEXT NOT NULL,
+    vector_size INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS tool_embedding_aliases (
+    alias TEXT PRIMARY KEY,
+    collection TEXT NOT NULL REFERENCES tool_embedding_collections(name)
+);
+
+-- Only the pgvector backend needs the extension, so servers without it can still run the
+-- router against Qdrant or the in-memory store.
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM pg_available_extensions WHERE name = 'vector') THEN
+        CREATE EXTENSION IF NOT EXISTS vector;
+
+        CREATE TABLE IF NOT EXISTS tool_embeddings (
+            collection TEXT NOT NULL REFERENCES tool_embedding_collections(name) ON DELETE CASCADE,
+            id TEXT NOT NULL,
+            payload JSONB NOT NULL,
+            name_embedding vector,
+            description_embedding vector,
+            schema_embedding vector,
+            PRIMARY KEY (collection, id)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_tool_embeddings_mcp_url ON tool_embeddings(collection, (payload->>'mcp_url'));
+    END IF;
+END
+$$;
@@ -0,0 +1,15 @@
+ALTER TABLE tool_call_results ADD COLUMN IF NOT EXISTS source TEXT NOT NULL DEFAULT 'agent';
+
+CREATE TABLE IF NOT EXISTS tool_probes (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    tool_name TEXT NOT NULL,
+    mcp_url TEXT NOT NULL,
+    arguments JSONB NOT NULL DEFAULT '{}',
+    expect_output_contains TEXT,
+    interval_seconds INTEGER NOT NULL,
+    last_run_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (tool_name, mcp_url)
+);
+
+CREATE INDEX IF NOT EXISTS idx_tool_probes_mcp_url ON tool_probes(mcp_url);
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS batches (
+    id TEXT PRIMARY KEY,
+    ttl_seconds BIGINT NOT NULL,
+    last_activity TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS batch_urls (
+    batch_id TEXT NOT NULL REFERENCES batches(id) ON DELETE CASCADE,
+    mcp_url TEXT NOT NULL,
+    PRIMARY KEY (batch_id, mcp_url)
+);
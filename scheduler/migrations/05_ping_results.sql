@@ -0,0 +1,23 @@
+CREATE TABLE IF NOT EXISTS ping_results (
+    id BIGSERIAL PRIMARY KEY,
+    mcp_url TEXT NOT NULL,
+    latency_ms DOUBLE PRECISION NOT NULL,
+    is_success BOOLEAN NOT NULL,
+    error TEXT,
+    timestamp TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_ping_results_mcp_url_timestamp ON ping_results(mcp_url, timestamp);
+
+-- Latency columns only cover successful pings.
+CREATE TABLE IF NOT EXISTS ping_rollups (
+    mcp_url TEXT NOT NULL,
+    bucket_seconds INTEGER NOT NULL,
+    bucket_start TIMESTAMPTZ NOT NULL,
+    ping_count BIGINT NOT NULL,
+    success_count BIGINT NOT NULL,
+    latency_sum_ms DOUBLE PRECISION NOT NULL,
+    min_latency_ms DOUBLE PRECISION,
+    max_latency_ms DOUBLE PRECISION,
+    PRIMARY KEY (mcp_url, bucket_seconds, bucket_start)
+);
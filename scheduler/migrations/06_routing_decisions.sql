@@ -0,0 +1,16 @@
+-- One row per tool returned by /search: the cluster it was picked from and why.
+CREATE TABLE IF NOT EXISTS routing_decisions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    batch_id TEXT NOT NULL,
+    query TEXT,
+    strategy TEXT NOT NULL,
+    rtt_weight DOUBLE PRECISION NOT NULL,
+    selected_tool_name TEXT NOT NULL,
+    selected_mcp_url TEXT NOT NULL,
+    candidates JSONB NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_routing_decisions_batch_id_timestamp ON routing_decisions(batch_id, timestamp);
+CREATE INDEX IF NOT EXISTS idx_routing_decisions_selected_timestamp
+    ON routing_decisions(selected_tool_name, selected_mcp_url, timestamp);
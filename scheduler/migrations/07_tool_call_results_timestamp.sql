@@ -0,0 +1 @@
+CREATE INDEX IF NOT EXISTS idx_tool_call_results_timestamp ON tool_call_results(timestamp);
@@ -0,0 +1,25 @@
+-- Latency columns only cover successful calls. Percentiles are exact within a bucket;
+-- readers combining buckets weight them by success_count.
+CREATE TABLE IF NOT EXISTS tool_call_rollups (
+    tool_name TEXT NOT NULL,
+    mcp_url TEXT NOT NULL,
+    bucket_seconds INTEGER NOT NULL,
+    bucket_start TIMESTAMPTZ NOT NULL,
+    call_count BIGINT NOT NULL,
+    error_count BIGINT NOT NULL,
+    success_count BIGINT NOT NULL,
+    latency_sum_ms DOUBLE PRECISION NOT NULL,
+    p50_latency_ms DOUBLE PRECISION,
+    p95_latency_ms DOUBLE PRECISION,
+    p99_latency_ms DOUBLE PRECISION,
+    last_called_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (tool_name, mcp_url, bucket_seconds, bucket_start)
+);
+
+CREATE INDEX IF NOT EXISTS idx_tool_call_rollups_bucket ON tool_call_rollups(bucket_seconds, bucket_start);
+
+-- Raw rows before rolled_until have been folded into the rollups of that resolution.
+CREATE TABLE IF NOT EXISTS tool_call_rollup_watermarks (
+    bucket_seconds INTEGER PRIMARY KEY,
+    rolled_until TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,13 @@
+ALTER TABLE tool_call_results
+    ADD COLUMN IF NOT EXISTS batch_id TEXT,
+    ADD COLUMN IF NOT EXISTS session_id TEXT,
+    ADD COLUMN IF NOT EXISTS request_bytes BIGINT,
+    ADD COLUMN IF NOT EXISTS response_bytes BIGINT,
+    ADD COLUMN IF NOT EXISTS error_category TEXT
+        CHECK (error_category IN ('timeout', 'transport', 'tool_error', 'validation')),
+    ADD COLUMN IF NOT EXISTS mcp_error_code INTEGER,
+    ADD COLUMN IF NOT EXISTS time_to_first_byte_ms BIGINT,
+    ADD COLUMN IF NOT EXISTS attributes JSONB;
+
+CREATE INDEX IF NOT EXISTS idx_tool_call_results_batch_id ON tool_call_results(batch_id);
+CREATE INDEX IF NOT EXISTS idx_tool_call_results_session_id ON tool_call_results(session_id);
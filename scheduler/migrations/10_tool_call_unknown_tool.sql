@@ -0,0 +1,2 @@
+ALTER TABLE tool_call_results
+    ADD COLUMN IF NOT EXISTS unknown_tool BOOLEAN NOT NULL DEFAULT FALSE;
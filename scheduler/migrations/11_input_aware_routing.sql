@@ -0,0 +1,9 @@
+-- Argument features of a call, e.g. its target domain, that input-aware routing conditions
+-- latency on.
+ALTER TABLE tool_call_results ADD COLUMN IF NOT EXISTS features JSONB;
+
+CREATE INDEX IF NOT EXISTS idx_tool_call_results_features ON tool_call_results USING GIN (features jsonb_path_ops);
+
+ALTER TABLE routing_decisions
+    ADD COLUMN IF NOT EXISTS mode TEXT NOT NULL DEFAULT 'latency',
+    ADD COLUMN IF NOT EXISTS features JSONB;
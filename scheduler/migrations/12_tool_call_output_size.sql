@@ -0,0 +1,11 @@
+-- Estimated LLM tokens of a call's output, for agents that count them rather than bytes.
+ALTER TABLE tool_call_results ADD COLUMN IF NOT EXISTS response_tokens BIGINT;
+
+-- Output sizes of successful calls that reported them, so readers can average across buckets.
+ALTER TABLE tool_call_rollups
+    ADD COLUMN IF NOT EXISTS response_bytes_sum DOUBLE PRECISION NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS response_bytes_count BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS response_tokens_sum DOUBLE PRECISION NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS response_tokens_count BIGINT NOT NULL DEFAULT 0;
+
+ALTER TABLE routing_decisions ADD COLUMN IF NOT EXISTS output_token_weight DOUBLE PRECISION NOT NULL DEFAULT 0;
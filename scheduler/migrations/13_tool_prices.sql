@@ -0,0 +1,25 @@
+-- Price of calling a tool, or every tool of a server when tool_name is NULL. A tool's own
+-- price takes precedence over its server's. per_unit is charged per unit of the call.
+CREATE TABLE IF NOT EXISTS tool_prices (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    mcp_url TEXT NOT NULL,
+    tool_name TEXT,
+    per_call DOUBLE PRECISION NOT NULL DEFAULT 0,
+    per_unit DOUBLE PRECISION NOT NULL DEFAULT 0,
+    unit TEXT CHECK (unit IN ('output_token', 'second')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE NULLS NOT DISTINCT (mcp_url, tool_name)
+);
+
+-- What a call cost at the prices of the time it was logged; NULL for unpriced tools.
+ALTER TABLE tool_call_results ADD COLUMN IF NOT EXISTS price DOUBLE PRECISION;
+
+ALTER TABLE tool_call_rollups ADD COLUMN IF NOT EXISTS price_sum DOUBLE PRECISION NOT NULL DEFAULT 0;
+
+ALTER TABLE batches
+    ADD COLUMN IF NOT EXISTS budget DOUBLE PRECISION,
+    ADD COLUMN IF NOT EXISTS spent DOUBLE PRECISION NOT NULL DEFAULT 0;
+
+ALTER TABLE routing_decisions
+    ADD COLUMN IF NOT EXISTS latency_target_ms DOUBLE PRECISION,
+    ADD COLUMN IF NOT EXISTS remaining_budget DOUBLE PRECISION;
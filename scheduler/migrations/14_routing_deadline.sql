@@ -0,0 +1 @@
+ALTER TABLE routing_decisions ADD COLUMN IF NOT EXISTS deadline_ms DOUBLE PRECISION;
@@ -0,0 +1,2 @@
+-- Retention deletes raw pings by age across all servers.
+CREATE INDEX IF NOT EXISTS idx_ping_results_timestamp ON ping_results(timestamp);
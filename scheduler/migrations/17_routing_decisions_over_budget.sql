@@ -0,0 +1,5 @@
+-- Clusters left out of a budget-aware search because no tool was affordable are recorded
+-- without a selected tool.
+ALTER TABLE routing_decisions
+    ALTER COLUMN selected_tool_name DROP NOT NULL,
+    ALTER COLUMN selected_mcp_url DROP NOT NULL;
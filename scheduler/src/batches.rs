@@ -0,0 +1,253 @@
+use std::{
+  collections::{HashMap, HashSet},
+  sync::Mutex,
+  time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use axum::{
+  Json,
+  extract::{Path, State},
+  http::StatusCode,
+  response::IntoResponse,
+};
+use sqlx::PgPool;
+use tracing::{error, info, instrument};
+
+use crate::{
+  ACTIVITY_PERSIST_TTL_DIVISOR, MAX_BATCH_TTL, TIMEOUT_DURATION,
+  reconnect::restore_server,
+  types::{AppState, BatchId, RenewBatchResponse},
+};
+
+/// A registration of one or more MCP servers. It is leased rather than fixed-length: it
+/// expires once it has been idle for `ttl`, and renewals, searches and logged calls on it
+/// count as activity.
+pub(crate) struct Batch {
+  pub(crate) urls: HashSet<String>,
+  pub(crate) ttl: Duration,
+  /// Behind its own lock so activity can be recorded under the state's read lock.
+  pub(crate) last_activity: Mutex<Instant>,
+  /// When `touch_batch` last wrote activity through to `batches.last_activity`.
+  pub(crate) persisted_activity: Mutex<Instant>,
+  /// Most the batch may spend on priced tool calls. What it spent so far is kept in
+  /// `batches.spent`.
+  pub(crate) budget: Option<f64>,
+}
+
+impl Batch {
+  /// `ttl_seconds` defaults to `TIMEOUT_DURATION` and is capped at `MAX_BATCH_TTL`.
+  pub(crate) fn new(urls: HashSet<String>, ttl_seconds: Option<u64>, budget: Option<f64>) -> Self {
+    let ttl = ttl_seconds.map(Duration::from_secs).unwrap_or(TIMEOUT_DURATION).min(MAX_BATCH_TTL);
+
+    Self {
+      urls,
+      ttl,
+      last_activity: Mutex::new(Instant::now()),
+      persisted_activity: Mutex::new(Instant::now()),
+      budget,
+    }
+  }
+
+  pub(crate) fn touch(&self) {
+    *self.last_activity.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+  }
+
+  /// Whether enough of the TTL passed since activity was last written through to write it
+  /// again. Claims the write when it did.
+  fn claim_activity_write(&self) -> bool {
+    let mut persisted_activity = self.persisted_activity.lock().unwrap_or_else(|e| e.into_inner());
+    if persisted_activity.elapsed() < self.ttl / ACTIVITY_PERSIST_TTL_DIVISOR {
+      return false;
+    }
+
+    *persisted_activity = Instant::now();
+    true
+  }
+
+  pub(crate) fn idle(&self) -> Duration {
+    self.last_activity.lock().unwrap_or_else(|e| e.into_inner()).elapsed()
+  }
+
+  pub(crate) fn is_expired(&self) -> bool {
+    self.idle() > self.ttl
+  }
+
+  pub(crate) fn expires_in(&self) -> Duration {
+    self.ttl.saturating_sub(self.idle())
+  }
+}
+
+/// Records activity on `batch_id`, if it is still registered. It is written through to
+/// Postgres only every `ACTIVITY_PERSIST_TTL_DIVISOR`th of the TTL.
+pub(crate) async fn touch_batch(state: &AppState, batch_id: &str) {
+  let pool = {
+    let app_data = state.read().await;
+    let Some(batch) = app_data.batch_map.get(batch_id) else {
+      return;
+    };
+    batch.touch();
+    if !batch.claim_activity_write() {
+      return;
+    }
+    app_data.pool.clone()
+  };
+
+  persist_activity(&pool, batch_id).await;
+}
+
+/// Batches are written through to Postgres so a restarted scheduler can pick them up again
+/// with `restore_batches`. Failures are logged; the in-memory registration stays authoritative.
+pub(crate) async fn persist_batch(pool: &PgPool, batch_id: &str, batch: &Batch) {
+  let urls: Vec<String> = batch.urls.iter().cloned().collect();
+
+  let result = async {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+      "INSERT INTO batches (id, ttl_seconds, budget) VALUES ($1, $2, $3)",
+      batch_id,
+      batch.ttl.as_secs() as i64,
+      batch.budget
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+      "INSERT INTO batch_urls (batch_id, mcp_url) SELECT $1, UNNEST($2::TEXT[])",
+      batch_id,
+      &urls
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await
+  }
+  .await;
+
+  if let Err(e) = result {
+    error!(batch_id, error = %e, "Failed to persist batch");
+  }
+}
+
+pub(crate) async fn persist_activity(pool: &PgPool, batch_id: &str) {
+  if let Err(e) = sqlx::query!("UPDATE batches SET last_activity = NOW() WHERE id = $1", batch_id)
+    .execute(pool)
+    .await
+  {
+    error!(batch_id, error = %e, "Failed to persist batch activity");
+  }
+}
+
+/// What is left of the budget of `batch_id`, or `None` if it has none.
+pub(crate) async fn remaining_budget(pool: &PgPool, batch_id: &str, budget: Option<f64>) -> Result<Option<f64>> {
+  let Some(budget) = budget else {
+    return Ok(None);
+  };
+
+  let spent = sqlx::query_scalar!("SELECT spent FROM batches WHERE id = $1", batch_id)
+    .fetch_optional(pool)
+    .await?
+    .unwrap_or_default();
+
+  Ok(Some(budget - spent))
+}
+
+pub(crate) async fn delete_batches(pool: &PgPool, batch_ids: &[BatchId]) {
+  if batch_ids.is_empty() {
+    return;
+  }
+
+  if let Err(e) = sqlx::query!("DELETE FROM batches WHERE id = ANY($1)", batch_ids)
+    .execute(pool)
+    .await
+  {
+    error!(batch_ids = ?batch_ids, error = %e, "Failed to delete batches");
+  }
+}
+
+/// Reloads the batches that were still alive when the scheduler stopped, keeping their
+/// remaining idle time, and reconnects to their servers in the background. Agents can keep
+/// using their batch ids; searches return tools again as servers come back.
+#[instrument(skip_all)]
+pub(crate) async fn restore_batches(state: &AppState) -> Result<()> {
+  let pool = state.read().await.pool.clone();
+
+  sqlx::query!("DELETE FROM batches WHERE last_activity + ttl_seconds * INTERVAL '1 second' < NOW()")
+    .execute(&pool)
+    .await?;
+
+  let rows = sqlx::query!(
+    r#"
+    SELECT b.id, b.ttl_seconds, b.budget, EXTRACT(EPOCH FROM NOW() - b.last_activity)::FLOAT8 AS "idle_seconds!", u.mcp_url
+    FROM batches b
+    JOIN batch_urls u ON u.batch_id = b.id
+    "#
+  )
+  .fetch_all(&pool)
+  .await?;
+
+  let mut batches: HashMap<BatchId, Batch> = HashMap::new();
+  for row in rows {
+    let batch = batches.entry(row.id).or_insert_with(|| {
+      let last_activity = Instant::now()
+        .checked_sub(Duration::from_secs_f64(row.idle_seconds.max(0.0)))
+        .unwrap_or_else(Instant::now);
+
+      Batch {
+        urls: HashSet::new(),
+        ttl: Duration::from_secs(row.ttl_seconds as u64),
+        last_activity: Mutex::new(last_activity),
+        persisted_activity: Mutex::new(last_activity),
+        budget: row.budget,
+      }
+    });
+    batch.urls.insert(row.mcp_url);
+  }
+
+  let urls: HashSet<String> = batches.values().flat_map(|batch| batch.urls.iter().cloned()).collect();
+  info!(batch_count = batches.len(), server_count = urls.len(), "Restoring batches");
+
+  state.write().await.batch_map.extend(batches);
+
+  for url in urls {
+    let state = state.clone();
+    tokio::spawn(async move {
+      restore_server(state, url).await;
+    });
+  }
+
+  Ok(())
+}
+
+#[instrument(skip(state))]
+pub(crate) async fn renew_batch(State(state): State<AppState>, Path(batch_id): Path<String>) -> impl IntoResponse {
+  let mut app_data = state.write().await;
+
+  let Some(batch) = app_data.batch_map.get_mut(&batch_id) else {
+    return (
+      StatusCode::NOT_FOUND,
+      Json(RenewBatchResponse {
+        message: format!("Batch ID {} not found or already expired.", batch_id),
+        expires_in_seconds: None,
+      }),
+    )
+      .into_response();
+  };
+
+  batch.touch();
+  let expires_in = batch.expires_in();
+  let pool = app_data.pool.clone();
+  drop(app_data);
+
+  persist_activity(&pool, &batch_id).await;
+
+  (
+    StatusCode::OK,
+    Json(RenewBatchResponse {
+      message: format!("Batch ID {} renewed.", batch_id),
+      expires_in_seconds: Some(expires_in.as_secs()),
+    }),
+  )
+    .into_response()
+}
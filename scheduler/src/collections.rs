@@ -0,0 +1,23 @@
+use crate::TOOL_COLLECTION_NAME;
+
+pub(crate) const NAME_VECTOR: &str = "name";
+pub(crate) const DESCRIPTION_VECTOR: &str = "description";
+pub(crate) const SCHEMA_VECTOR: &str = "schema";
+
+/// Bumped whenever the set of vectors stored per point changes, so a new layout never
+/// reuses a collection created for an older one.
+const COLLECTION_LAYOUT_VERSION: u32 = 2;
+
+/// Name of the physical collection holding vectors produced by `model`.
+/// `TOOL_COLLECTION_NAME` is an alias pointing at one of these.
+pub(crate) fn collection_name_for(model: &str, vector_size: u64) -> String {
+  let model: String = model
+    .chars()
+    .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+    .collect();
+
+  format!(
+    "{}__v{}__{}__{}",
+    TOOL_COLLECTION_NAME, COLLECTION_LAYOUT_VERSION, model, vector_size
+  )
+}
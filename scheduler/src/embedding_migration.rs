@@ -0,0 +1,244 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use tracing::{error, info, instrument};
+
+use crate::{
+  TOOL_COLLECTION_NAME,
+  collections::DESCRIPTION_VECTOR,
+  embedding_template::{ToolDescriptor, embed_tool},
+  types::{AppState, MigrateEmbeddingsRequest, MigrateEmbeddingsResponse},
+  vector_store::{ToolPoint, VectorStore},
+};
+
+/// Re-embeds every stored tool with a new model into a fresh collection, then swaps the
+/// `TOOL_COLLECTION_NAME` alias over to it. The previous collection is left in place so
+/// the migration can be rolled back by pointing the alias at it again. Migrating to the
+/// current model moves a collection from an older vector layout onto the current one.
+#[instrument(skip_all, fields(model = %payload.model))]
+pub(crate) async fn migrate_embeddings(State(state): State<AppState>, Json(payload): Json<MigrateEmbeddingsRequest>) -> impl IntoResponse {
+  let (store, previous_model, previous_template) = {
+    let app_data = state.read().await;
+    (
+      app_data.vector_store.clone(),
+      app_data.embedding_model.clone(),
+      app_data.embedding_template.clone(),
+    )
+  };
+
+  let template = payload.template.clone().unwrap_or_else(|| previous_template.clone());
+  let template_changed = template != previous_template;
+
+  if payload.model.is_empty() {
+    return (
+      StatusCode::BAD_REQUEST,
+      Json(MigrateEmbeddingsResponse {
+        message: "No embedding model provided.".to_string(),
+        previous_model,
+        model: payload.model,
+        collection: None,
+        tools_migrated: 0,
+      }),
+    )
+      .into_response();
+  }
+
+  info!(previous_model = %previous_model, "Starting embedding migration");
+
+  let source = match store.active_collection().await {
+    Ok(source) => source,
+    Err(e) => {
+      error!(error = %e, "Failed to resolve active collection");
+      return migration_error(StatusCode::INTERNAL_SERVER_ERROR, previous_model, payload.model, e);
+    }
+  };
+
+  let Some(source) = source else {
+    // Nothing stored yet; the next registration creates the collection for the new model.
+    let mut app_data = state.write().await;
+    app_data.embedding_model = payload.model.clone();
+    app_data.embedding_template = template;
+    drop(app_data);
+    return (
+      StatusCode::OK,
+      Json(MigrateEmbeddingsResponse {
+        message: format!("No tools stored. Switched embedding model to {}.", payload.model),
+        previous_model,
+        model: payload.model,
+        collection: None,
+        tools_migrated: 0,
+      }),
+    )
+      .into_response();
+  };
+
+  // Bulk of the work happens without holding the lock so searches keep being served.
+  let mut migrated = HashSet::new();
+  let target = match copy_points(
+    store.as_ref(),
+    &source,
+    None,
+    &payload.model,
+    &template,
+    template_changed,
+    &mut migrated,
+  )
+  .await
+  {
+    Ok(target) => target,
+    Err(e) => {
+      error!(error = %e, "Embedding migration failed");
+      return migration_error(StatusCode::BAD_GATEWAY, previous_model, payload.model, e);
+    }
+  };
+
+  // Registrations hold the write lock while storing tools, so once we have it no new
+  // points can land in the source collection. Catch up on anything added meanwhile and swap.
+  let mut app_data = state.write().await;
+
+  let result = async {
+    let target = copy_points(
+      store.as_ref(),
+      &source,
+      target,
+      &payload.model,
+      &template,
+      template_changed,
+      &mut migrated,
+    )
+    .await?;
+    if let Some(target) = &target {
+      if *target != source {
+        remove_stale_points(store.as_ref(), &source, target).await?;
+      }
+      store.activate_collection(target).await?;
+    }
+    Ok::<_, anyhow::Error>(target)
+  }
+  .await;
+
+  let target = match result {
+    Ok(target) => target,
+    Err(e) => {
+      error!(error = %e, "Embedding migration failed while swapping");
+      return migration_error(StatusCode::BAD_GATEWAY, previous_model, payload.model, e);
+    }
+  };
+
+  app_data.embedding_model = payload.model.clone();
+  app_data.embedding_template = template;
+  drop(app_data);
+
+  info!(tools_migrated = migrated.len(), "Embedding migration complete");
+
+  let previous_collection = if target.as_deref() == Some(source.as_str()) {
+    "Embeddings were replaced in place.".to_string()
+  } else if source == TOOL_COLLECTION_NAME {
+    "Legacy collection was replaced by an alias.".to_string()
+  } else {
+    format!("Previous collection {} was kept.", source)
+  };
+
+  (
+    StatusCode::OK,
+    Json(MigrateEmbeddingsResponse {
+      message: format!(
+        "Migrated {} tools from {} to {}. {}",
+        migrated.len(),
+        previous_model,
+        payload.model,
+        previous_collection
+      ),
+      previous_model,
+      model: payload.model,
+      collection: target,
+      tools_migrated: migrated.len(),
+    }),
+  )
+    .into_response()
+}
+
+fn migration_error(status: StatusCode, previous_model: String, model: String, error: anyhow::Error) -> axum::response::Response {
+  (
+    status,
+    Json(MigrateEmbeddingsResponse {
+      message: format!("Embedding migration failed: {}", error),
+      previous_model,
+      model,
+      collection: None,
+      tools_migrated: 0,
+    }),
+  )
+    .into_response()
+}
+
+/// Re-embeds every point of `source` not yet in `migrated` into the collection for `model`,
+/// creating it from the first embedding's dimension if `target` is not known yet. When only
+/// the template changes the collection stays the same and points are overwritten in place.
+async fn copy_points(
+  store: &dyn VectorStore,
+  source: &str,
+  mut target: Option<String>,
+  model: &str,
+  template: &str,
+  allow_in_place: bool,
+  migrated: &mut HashSet<String>,
+) -> Result<Option<String>> {
+  let mut points = Vec::new();
+
+  for point in store.scroll(source, None, false).await? {
+    if migrated.contains(&point.id) {
+      continue;
+    }
+
+    let descriptor = ToolDescriptor::from_payload(&point.payload);
+    let mcp_url = point.payload_str("mcp_url").unwrap_or_default();
+    let (vectors, payload) = embed_tool(&descriptor, &mcp_url, template, model).await?;
+
+    if target.is_none() {
+      let vector_size = vectors.get(DESCRIPTION_VECTOR).map(|v| v.len()).unwrap_or_default() as u64;
+      let collection = store.create_collection(model, vector_size).await?;
+      if collection == source && !allow_in_place {
+        anyhow::bail!("Model {} already backs collection {}", model, source);
+      }
+      target = Some(collection);
+    }
+
+    points.push(ToolPoint {
+      id: point.id,
+      vectors,
+      payload,
+    });
+  }
+
+  if let (Some(target), false) = (&target, points.is_empty()) {
+    let ids: Vec<String> = points.iter().map(|p| p.id.clone()).collect();
+    store.upsert(target, points).await?;
+    migrated.extend(ids);
+  }
+
+  Ok(target)
+}
+
+/// Deletes the points of `target` that are not in `source`. The target is reused if its model
+/// backed a collection before, e.g. when migrating A to B and back to A, and may still hold
+/// tools removed since.
+async fn remove_stale_points(store: &dyn VectorStore, source: &str, target: &str) -> Result<()> {
+  let current: HashSet<String> = store.scroll(source, None, false).await?.into_iter().map(|point| point.id).collect();
+
+  let stale: Vec<String> = store
+    .scroll(target, None, false)
+    .await?
+    .into_iter()
+    .map(|point| point.id)
+    .filter(|id| !current.contains(id))
+    .collect();
+
+  if !stale.is_empty() {
+    info!(stale_points = stale.len(), "Removing stale points from reused collection");
+    store.delete(target, stale).await?;
+  }
+
+  Ok(())
+}
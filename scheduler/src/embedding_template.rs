@@ -0,0 +1,274 @@
+use anyhow::Result;
+use rmcp::model::{JsonObject, Tool, ToolAnnotations};
+
+use crate::{
+  embeddings::{generate_tool_vectors, render_input_schema, render_schema},
+  vector_store::{Payload, ToolVectors},
+};
+
+/// Everything about a tool that can go into its embedding text. Built either from the
+/// tool listing of a live server or from a stored point payload, so registration and
+/// re-embedding render exactly the same text.
+pub(crate) struct ToolDescriptor {
+  pub(crate) name: String,
+  pub(crate) title: Option<String>,
+  pub(crate) description: String,
+  pub(crate) input_schema: JsonObject,
+  pub(crate) output_schema: Option<JsonObject>,
+  pub(crate) annotations: Option<ToolAnnotations>,
+}
+
+impl ToolDescriptor {
+  pub(crate) fn from_tool(tool: &Tool) -> Self {
+    Self {
+      name: tool.name.clone().into_owned(),
+      title: tool.title.clone(),
+      description: tool.description.clone().map(|d| d.into_owned()).unwrap_or_default(),
+      input_schema: (*tool.input_schema).clone(),
+      output_schema: tool.output_schema.as_ref().map(|s| (**s).clone()),
+      annotations: tool.annotations.clone(),
+    }
+  }
+
+  pub(crate) fn from_payload(payload: &Payload) -> Self {
+    let string_field = |key: &str| payload.get(key).cloned();
+
+    Self {
+      name: string_field("name").unwrap_or_default(),
+      title: string_field("title"),
+      description: string_field("description").unwrap_or_default(),
+      input_schema: string_field("inputSchema")
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default(),
+      output_schema: string_field("outputSchema").and_then(|s| serde_json::from_str(&s).ok()),
+      annotations: string_field("annotations").and_then(|s| serde_json::from_str(&s).ok()),
+    }
+  }
+
+  /// Payload stored alongside the tool's vectors, including the texts that were embedded.
+  pub(crate) fn to_payload(&self, mcp_url: &str, embedding_text: &str, schema_text: &str) -> Payload {
+    let mut payload_map = Payload::new();
+    let mut insert = |key: &str, value: String| {
+      payload_map.insert(key.to_string(), value);
+    };
+
+    insert("name", self.name.clone());
+    insert("description", self.description.clone());
+    insert("mcp_url", mcp_url.to_string());
+    insert(
+      "inputSchema",
+      serde_json::to_string(&self.input_schema).unwrap_or_else(|_| "{}".to_string()),
+    );
+    if let Some(title) = &self.title {
+      insert("title", title.clone());
+    }
+    if let Some(output_schema) = self.output_schema.as_ref().and_then(|s| serde_json::to_string(s).ok()) {
+      insert("outputSchema", output_schema);
+    }
+    if let Some(annotations) = self.annotations.as_ref().and_then(|a| serde_json::to_string(a).ok()) {
+      insert("annotations", annotations);
+    }
+    insert("embedding_text", embedding_text.to_string());
+    insert("schema_text", schema_text.to_string());
+
+    payload_map
+  }
+
+  fn field(&self, placeholder: &str) -> Option<String> {
+    let value = match placeholder {
+      "name" => self.name.clone(),
+      "title" => self
+        .title
+        .clone()
+        .or_else(|| self.annotations.as_ref().and_then(|a| a.title.clone()))
+        .unwrap_or_default(),
+      "description" => self.description.clone(),
+      "annotations" => self.annotations.as_ref().map(render_annotations).unwrap_or_default(),
+      "parameters" => render_schema(&self.input_schema),
+      "output_schema" => self.output_schema.as_ref().map(render_schema).unwrap_or_default(),
+      _ => return None,
+    };
+
+    Some(value)
+  }
+}
+
+fn render_annotations(annotations: &ToolAnnotations) -> String {
+  [
+    (annotations.read_only_hint, "read-only"),
+    (annotations.destructive_hint, "destructive"),
+    (annotations.idempotent_hint, "idempotent"),
+    (annotations.open_world_hint, "open-world"),
+  ]
+  .into_iter()
+  .filter(|(hint, _)| *hint == Some(true))
+  .map(|(_, label)| label)
+  .collect::<Vec<_>>()
+  .join(", ")
+}
+
+/// Renders the embedding text of a tool.
+///
+/// `{name}`, `{title}`, `{description}`, `{annotations}`, `{parameters}` and
+/// `{output_schema}` are replaced by the corresponding tool fields. Text wrapped in
+/// `[...]` is only kept when every placeholder inside it is non-empty, so the default
+/// `{name}[: {description}]` falls back to just the name for undocumented tools.
+/// Unknown placeholders are left as written.
+pub(crate) fn render_template(template: &str, tool: &ToolDescriptor) -> String {
+  let mut output = String::new();
+  let mut section = String::new();
+  let mut section_complete = true;
+  let mut in_section = false;
+  let mut chars = template.chars();
+
+  while let Some(c) = chars.next() {
+    match c {
+      '[' if !in_section => {
+        in_section = true;
+        section_complete = true;
+        section.clear();
+      }
+      ']' if in_section => {
+        in_section = false;
+        if section_complete {
+          output.push_str(&section);
+        }
+      }
+      '{' => {
+        let placeholder: String = chars.by_ref().take_while(|&c| c != '}').collect();
+        let text = match tool.field(&placeholder) {
+          Some(value) => {
+            if value.is_empty() {
+              section_complete = false;
+            }
+            value
+          }
+          None => format!("{{{}}}", placeholder),
+        };
+        if in_section {
+          section.push_str(&text)
+        } else {
+          output.push_str(&text)
+        }
+      }
+      c => {
+        if in_section {
+          section.push(c)
+        } else {
+          output.push(c)
+        }
+      }
+    }
+  }
+
+  if in_section && section_complete {
+    output.push_str(&section);
+  }
+
+  output.trim().to_string()
+}
+
+/// Renders `tool` with `template` and embeds it, returning the named vectors and the payload
+/// to store with them.
+pub(crate) async fn embed_tool(tool: &ToolDescriptor, mcp_url: &str, template: &str, model: &str) -> Result<(ToolVectors, Payload)> {
+  let embedding_text = render_template(template, tool);
+  let schema_text = render_input_schema(&tool.input_schema);
+
+  let vectors = generate_tool_vectors(&tool.name, &embedding_text, &schema_text, model).await?;
+
+  Ok((vectors, tool.to_payload(mcp_url, &embedding_text, &schema_text)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn tool(description: &str, title: Option<&str>, annotations: Option<ToolAnnotations>) -> ToolDescriptor {
+    ToolDescriptor {
+      name: "get_weather".to_string(),
+      title: title.map(str::to_string),
+      description: description.to_string(),
+      input_schema: JsonObject::new(),
+      output_schema: None,
+      annotations,
+    }
+  }
+
+  #[test]
+  fn render_template_cases() {
+    let read_only = ToolAnnotations {
+      title: Some("Weather lookup".to_string()),
+      read_only_hint: Some(true),
+      idempotent_hint: Some(true),
+      destructive_hint: Some(false),
+      ..Default::default()
+    };
+
+    let cases = [
+      (
+        "default template",
+        "{name}[: {description}]",
+        tool("Current weather", None, None),
+        "get_weather: Current weather",
+      ),
+      (
+        "undocumented tool drops the section",
+        "{name}[: {description}]",
+        tool("", None, None),
+        "get_weather",
+      ),
+      (
+        "title",
+        "{title} ({name})",
+        tool("", Some("Weather"), None),
+        "Weather (get_weather)",
+      ),
+      (
+        "title falls back to the annotations",
+        "{title}",
+        tool("", None, Some(read_only.clone())),
+        "Weather lookup",
+      ),
+      (
+        "annotation hints",
+        "{name}[ ({annotations})]",
+        tool("", None, Some(read_only)),
+        "get_weather (read-only, idempotent)",
+      ),
+      (
+        "missing annotations drop the section",
+        "{name}[ ({annotations})]",
+        tool("", None, None),
+        "get_weather",
+      ),
+      (
+        "one empty placeholder drops the whole section",
+        "{name}[ - {title}: {description}]",
+        tool("Current weather", None, None),
+        "get_weather",
+      ),
+      (
+        "unknown placeholders are kept",
+        "{name} {unknown}",
+        tool("", None, None),
+        "get_weather {unknown}",
+      ),
+      (
+        "unterminated section",
+        "{name}[: {description}",
+        tool("Current weather", None, None),
+        "get_weather: Current weather",
+      ),
+      (
+        "surrounding whitespace is trimmed",
+        "  {name}  ",
+        tool("", None, None),
+        "get_weather",
+      ),
+    ];
+
+    for (name, template, tool, expected) in cases {
+      assert_eq!(render_template(template, &tool), expected, "{name}");
+    }
+  }
+}
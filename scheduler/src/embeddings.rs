@@ -1,8 +1,13 @@
+use std::collections::HashMap;
+
 use anyhow::Result;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
-use crate::{DEFAULT_EMBEDDING_MODEL, OPENROUTER_API_KEY, OPENROUTER_EMBEDDINGS_URL};
+use crate::{
+  OPENROUTER_API_KEY, OPENROUTER_EMBEDDINGS_URL,
+  collections::{DESCRIPTION_VECTOR, NAME_VECTOR, SCHEMA_VECTOR},
+};
 
 #[derive(Serialize)]
 struct EmbeddingRequest {
@@ -18,17 +23,48 @@ struct EmbeddingResponse {
 #[derive(Deserialize)]
 struct EmbeddingData {
   embedding: Vec<f32>,
+  #[serde(default)]
+  index: usize,
 }
 
-pub async fn generate_embedding(description: &str) -> Result<Vec<f32>> {
-  generate_embedding_with_model(description, DEFAULT_EMBEDDING_MODEL).await
+pub async fn generate_embedding_with_model(description: &str, model: &str) -> Result<Vec<f32>> {
+  generate_embeddings_with_model(vec![description.to_string()], model)
+    .await?
+    .into_iter()
+    .next()
+    .ok_or_else(|| anyhow::anyhow!("No embedding data found in response"))
 }
 
-pub async fn generate_embedding_with_model(description: &str, model: &str) -> Result<Vec<f32>> {
+/// Embeds the name, description text and rendered input schema of a tool in a single
+/// request, keyed by the named vector each one is stored under.
+pub async fn generate_tool_vectors(
+  name: &str,
+  description_text: &str,
+  schema_text: &str,
+  model: &str,
+) -> Result<HashMap<String, Vec<f32>>> {
+  let embeddings =
+    generate_embeddings_with_model(vec![name.to_string(), description_text.to_string(), schema_text.to_string()], model).await?;
+
+  if embeddings.len() != 3 {
+    anyhow::bail!("Expected 3 embeddings for tool {}, got {}", name, embeddings.len());
+  }
+
+  Ok(
+    [NAME_VECTOR, DESCRIPTION_VECTOR, SCHEMA_VECTOR]
+      .into_iter()
+      .map(str::to_string)
+      .zip(embeddings)
+      .collect(),
+  )
+}
+
+/// Embeds all inputs in one request, returned in input order.
+pub async fn generate_embeddings_with_model(inputs: Vec<String>, model: &str) -> Result<Vec<Vec<f32>>> {
   let client = Client::new();
   let request_body = EmbeddingRequest {
     model: model.to_string(),
-    input: vec![description.to_string()],
+    input: inputs,
   };
 
   let response = client
@@ -45,11 +81,65 @@ pub async fn generate_embedding_with_model(description: &str, model: &str) -> Re
     anyhow::bail!("OpenRouter API error ({}): {}", status, error_text);
   }
 
-  let embedding_response: EmbeddingResponse = response.json().await?;
+  let mut embedding_response: EmbeddingResponse = response.json().await?;
 
-  embedding_response
-    .data
-    .first()
-    .map(|data| data.embedding.clone())
-    .ok_or_else(|| anyhow::anyhow!("No embedding data found in response"))
+  if embedding_response.data.is_empty() {
+    anyhow::bail!("No embedding data found in response");
+  }
+
+  embedding_response.data.sort_by_key(|data| data.index);
+
+  Ok(embedding_response.data.into_iter().map(|data| data.embedding).collect())
+}
+
+/// Renders a tool's JSON input schema as plain text so its parameters can be embedded.
+pub fn render_input_schema(schema: &serde_json::Map<String, serde_json::Value>) -> String {
+  let rendered = render_schema(schema);
+  if rendered.is_empty() {
+    "no parameters".to_string()
+  } else {
+    rendered
+  }
+}
+
+/// Renders the properties of a JSON object schema one per line, or an empty string if it
+/// has none.
+///
+/// ```text
+/// url (string, required): The page to scrape
+/// timeout (number): Seconds to wait
+/// ```
+pub fn render_schema(schema: &serde_json::Map<String, serde_json::Value>) -> String {
+  let required: Vec<&str> = schema
+    .get("required")
+    .and_then(|r| r.as_array())
+    .map(|r| r.iter().filter_map(|v| v.as_str()).collect())
+    .unwrap_or_default();
+
+  let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) else {
+    return String::new();
+  };
+
+  properties
+    .iter()
+    .map(|(name, property)| {
+      let mut qualifiers = Vec::new();
+      if let Some(kind) = property.get("type").and_then(|t| t.as_str()) {
+        qualifiers.push(kind);
+      }
+      if required.contains(&name.as_str()) {
+        qualifiers.push("required");
+      }
+
+      let mut line = name.clone();
+      if !qualifiers.is_empty() {
+        line.push_str(&format!(" ({})", qualifiers.join(", ")));
+      }
+      if let Some(description) = property.get("description").and_then(|d| d.as_str()) {
+        line.push_str(&format!(": {}", description));
+      }
+      line
+    })
+    .collect::<Vec<_>>()
+    .join("\n")
 }
@@ -0,0 +1,53 @@
+use std::time::Instant;
+
+use serde::Serialize;
+
+use crate::{DOWN_AFTER_FAILURES, MAX_PING_HISTORY};
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum HealthState {
+  /// The last ping succeeded.
+  Healthy,
+  /// Recent pings failed, but fewer than `DOWN_AFTER_FAILURES` in a row.
+  Degraded,
+  /// At least `DOWN_AFTER_FAILURES` pings in a row failed. Its tools are left out of `/search`.
+  Down,
+}
+
+pub(crate) struct PingFailure {
+  pub(crate) at: Instant,
+  pub(crate) error: String,
+}
+
+/// Ping outcomes of a monitored server. Failed pings are kept here instead of in
+/// `latency_history`, so they do not skew the latency average.
+#[derive(Default)]
+pub(crate) struct ServerHealth {
+  pub(crate) consecutive_failures: u32,
+  pub(crate) last_success: Option<Instant>,
+  pub(crate) failure_history: Vec<PingFailure>,
+}
+
+impl ServerHealth {
+  pub(crate) fn state(&self) -> HealthState {
+    match self.consecutive_failures {
+      0 => HealthState::Healthy,
+      n if n < DOWN_AFTER_FAILURES => HealthState::Degraded,
+      _ => HealthState::Down,
+    }
+  }
+
+  pub(crate) fn record_success(&mut self) {
+    self.consecutive_failures = 0;
+    self.last_success = Some(Instant::now());
+  }
+
+  pub(crate) fn record_failure(&mut self, error: String) {
+    self.consecutive_failures += 1;
+    self.failure_history.push(PingFailure { at: Instant::now(), error });
+    if self.failure_history.len() > MAX_PING_HISTORY {
+      self.failure_history.remove(0);
+    }
+  }
+}
@@ -2,99 +2,149 @@ use std::time::Duration;
 use std::time::Instant;
 
 use rmcp::model::{ClientRequest, PingRequest};
+use tracing::{error, info, instrument, warn};
 
 use crate::{
   HEARTBEAT_INTERVAL_SECONDS, MAX_PING_HISTORY, TIMEOUT_DURATION,
-  types::{AppState, DynamicMcpClient},
+  batches::delete_batches,
+  ping_history::record_ping,
+  probes::run_due_probes,
+  prometheus_metrics,
+  reconnect::{reconnect_server, should_reconnect},
+  types::{AppData, AppState, DynamicMcpClient},
 };
 
 pub(crate) async fn heartbeat_service(state: AppState) {
   let mut interval = tokio::time::interval(Duration::from_secs(HEARTBEAT_INTERVAL_SECONDS));
 
-  println!(
-    "Heartbeat service started. Pinging every {} seconds. Registration timeout: {}s. Ping history size: {}",
-    HEARTBEAT_INTERVAL_SECONDS,
-    TIMEOUT_DURATION.as_secs(),
-    MAX_PING_HISTORY
+  info!(
+    interval_seconds = HEARTBEAT_INTERVAL_SECONDS,
+    default_batch_ttl_seconds = TIMEOUT_DURATION.as_secs(),
+    ping_history_size = MAX_PING_HISTORY,
+    "Heartbeat service started"
   );
 
   loop {
     interval.tick().await;
+    heartbeat_tick(&state).await;
+  }
+}
 
-    let mut clients_to_ping: Vec<(String, DynamicMcpClient)> = Vec::new();
+#[instrument(skip_all)]
+async fn heartbeat_tick(state: &AppState) {
+  let mut clients_to_ping: Vec<(String, DynamicMcpClient)> = Vec::new();
+  let mut urls_to_reconnect = Vec::new();
 
-    let mut app_data = state.write().await;
-    let servers = &mut app_data.servers;
+  let mut app_data = state.write().await;
+  let AppData { servers, batch_map, .. } = &mut *app_data;
+
+  let mut expired_batches = Vec::new();
+  batch_map.retain(|id, batch| {
+    let is_expired = batch.is_expired();
+    if is_expired {
+      expired_batches.push(id.clone());
+      info!(
+        batch_id = %id,
+        idle_seconds = batch.idle().as_secs(),
+        "Batch timed out without activity"
+      );
+    }
+    !is_expired
+  });
 
-    let mut urls_to_remove = Vec::new();
+  let mut urls_to_remove = Vec::new();
 
-    for (url, status) in servers.iter_mut() {
-      let original_reg_count = status.active_batches.len();
+  for (url, status) in servers.iter_mut() {
+    let original_reg_count = status.active_batches.len();
 
-      status.active_batches.retain(|id, time| {
-        let is_expired = time.elapsed() > TIMEOUT_DURATION;
-        if is_expired {
-          println!("Batch ID {} for {} timed out after {}s.", id, url, time.elapsed().as_secs());
-        }
-        !is_expired
-      });
-
-      if status.active_batches.is_empty() {
-        urls_to_remove.push(url.clone());
-      } else if status.active_batches.len() < original_reg_count {
-        println!(
-          "Server {} still monitored. {} batch ID(s) remaining.",
-          url,
-          status.active_batches.len()
-        );
-      }
+    status.active_batches.retain(|id| batch_map.contains_key(id));
 
-      if !status.active_batches.is_empty() {
-        clients_to_ping.push((url.clone(), status.client.clone()));
-      }
+    if status.active_batches.is_empty() {
+      urls_to_remove.push(url.clone());
+    } else if status.active_batches.len() < original_reg_count {
+      info!(
+        mcp_url = %url,
+        remaining_batches = status.active_batches.len(),
+        "Server still monitored"
+      );
     }
 
-    for url in urls_to_remove {
-      if servers.remove(&url).is_some() {
-        println!("Monitoring stopped for {} (all batch IDs timed out).", url);
-      }
+    if status.active_batches.is_empty() {
+      continue;
     }
 
-    drop(app_data);
+    if should_reconnect(status) {
+      status.reconnect.in_progress = true;
+      urls_to_reconnect.push(url.clone());
+    } else if !status.reconnect.in_progress && !status.client.is_transport_closed() {
+      clients_to_ping.push((url.clone(), status.client.clone()));
+    }
+  }
 
-    for (url, client) in clients_to_ping {
-      let app_state_clone = state.clone();
-      tokio::spawn(async move {
-        ping_server(app_state_clone, url, client).await;
-      });
+  for url in urls_to_remove {
+    if servers.remove(&url).is_some() {
+      info!(mcp_url = %url, "Monitoring stopped, all batch IDs timed out");
     }
   }
+
+  let pool = app_data.pool.clone();
+  drop(app_data);
+
+  delete_batches(&pool, &expired_batches).await;
+
+  // The spans of these tasks are created here, so they are nested under the tick's span.
+  for url in urls_to_reconnect {
+    tokio::spawn(reconnect_server(state.clone(), url));
+  }
+
+  for (url, client) in clients_to_ping {
+    tokio::spawn(ping_server(state.clone(), url.clone(), client.clone()));
+    tokio::spawn(run_due_probes(state.clone(), url, client));
+  }
 }
 
+#[instrument(skip(state, client), fields(mcp_url = %url))]
 async fn ping_server(state: AppState, url: String, client: DynamicMcpClient) {
   let start_time = Instant::now();
 
   let result = client.send_request(ClientRequest::PingRequest(PingRequest::default())).await;
 
   let duration = start_time.elapsed();
+  let error = result.err().map(|e| format!("{:?}", e));
 
   let mut app_data = state.write().await;
+  let pool = app_data.pool.clone();
 
   if let Some(status) = app_data.servers.get_mut(&url) {
-    match result {
-      Ok(_) => {
-        println!("Ping SUCCESS for {}: {:#?}", url, duration);
+    let previous_state = status.health.state();
+
+    match &error {
+      None => {
+        info!(latency_ms = duration.as_secs_f64() * 1000.0, "Ping succeeded");
+
+        status.health.record_success();
+        status.latency_history.push(duration);
+        if status.latency_history.len() > MAX_PING_HISTORY {
+          status.latency_history.remove(0);
+        }
       }
-      Err(e) => {
-        eprintln!("Ping FAILED for {}: Error: {:?}", url, e);
+      Some(e) => {
+        warn!(error = %e, "Ping failed");
+        status.health.record_failure(e.clone());
       }
     }
 
-    status.latency_history.push(duration);
-    if status.latency_history.len() > MAX_PING_HISTORY {
-      status.latency_history.remove(0);
+    let state = status.health.state();
+    if state != previous_state {
+      info!(health = ?state, previous_health = ?previous_state, "Server health changed");
     }
   } else {
-    println!("Ping result received for {}, but server is no longer monitored.", url);
+    info!("Ping result received, but server is no longer monitored");
+  }
+  drop(app_data);
+
+  prometheus_metrics::record_ping(&url, duration, error.is_none());
+  if let Err(e) = record_ping(&pool, &url, duration, error).await {
+    error!(error = %e, "Failed to record ping");
   }
 }
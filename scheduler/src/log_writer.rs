@@ -0,0 +1,120 @@
+use sqlx::PgPool;
+use tokio::{
+  sync::{mpsc, oneshot},
+  task::JoinHandle,
+};
+use tracing::{error, info, warn};
+
+use crate::{
+  LOG_BATCH_CHUNK_SIZE, LOG_ENQUEUE_TIMEOUT, LOG_QUEUE_CAPACITY, LOG_WRITE_ATTEMPTS, LOG_WRITE_RETRY_DELAY,
+  prometheus_metrics::record_dropped_tool_calls,
+  tool_metrics::{LogToolCallRequest, insert_tool_calls},
+};
+
+/// Queues logged tool calls for the background writer. Cloning is cheap; all clones feed the
+/// same bounded queue.
+#[derive(Clone)]
+pub(crate) struct LogWriter {
+  sender: mpsc::Sender<LogToolCallRequest>,
+}
+
+/// The queue stayed full, or the writer has stopped.
+#[derive(Debug)]
+pub(crate) struct QueueFull;
+
+/// Stops the background writer once everything queued so far is written.
+pub(crate) struct LogWriterHandle {
+  shutdown: oneshot::Sender<()>,
+  task: JoinHandle<()>,
+}
+
+impl LogWriter {
+  /// Starts the writer. It inserts whatever has queued up since its last insert, up to
+  /// `LOG_BATCH_CHUNK_SIZE` records per statement.
+  pub(crate) fn spawn(pool: PgPool) -> (LogWriter, LogWriterHandle) {
+    let (sender, receiver) = mpsc::channel(LOG_QUEUE_CAPACITY);
+    let (shutdown, shutdown_receiver) = oneshot::channel();
+    let task = tokio::spawn(run(pool, receiver, shutdown_receiver));
+
+    (LogWriter { sender }, LogWriterHandle { shutdown, task })
+  }
+
+  /// Waits up to `LOG_ENQUEUE_TIMEOUT` for room in the queue, so callers can report
+  /// backpressure to the agent once it stays full.
+  pub(crate) async fn enqueue(&self, record: LogToolCallRequest) -> Result<(), QueueFull> {
+    self.sender.send_timeout(record, LOG_ENQUEUE_TIMEOUT).await.map_err(|_| QueueFull)
+  }
+
+  /// Like `enqueue`, without waiting for room.
+  pub(crate) fn try_enqueue(&self, record: LogToolCallRequest) -> Result<(), QueueFull> {
+    self.sender.try_send(record).map_err(|_| QueueFull)
+  }
+
+  pub(crate) fn queued(&self) -> usize {
+    self.sender.max_capacity() - self.sender.capacity()
+  }
+
+  /// A writer without the background task, so its queue fills up. Records can be taken from
+  /// the returned receiver.
+  #[cfg(test)]
+  pub(crate) fn stalled(capacity: usize) -> (LogWriter, mpsc::Receiver<LogToolCallRequest>) {
+    let (sender, receiver) = mpsc::channel(capacity);
+    (LogWriter { sender }, receiver)
+  }
+}
+
+impl LogWriterHandle {
+  pub(crate) async fn flush_and_stop(self) {
+    let _ = self.shutdown.send(());
+    if let Err(e) = self.task.await {
+      error!(error = %e, "Log writer stopped abnormally");
+    }
+  }
+}
+
+async fn run(pool: PgPool, mut receiver: mpsc::Receiver<LogToolCallRequest>, mut shutdown: oneshot::Receiver<()>) {
+  let mut records = Vec::with_capacity(LOG_BATCH_CHUNK_SIZE);
+
+  loop {
+    tokio::select! {
+      received = receiver.recv_many(&mut records, LOG_BATCH_CHUNK_SIZE) => {
+        if received == 0 {
+          return;
+        }
+        write(&pool, &mut records).await;
+      }
+      _ = &mut shutdown => break,
+    }
+  }
+
+  receiver.close();
+  let mut flushed = 0;
+  while receiver.recv_many(&mut records, LOG_BATCH_CHUNK_SIZE).await > 0 {
+    flushed += records.len();
+    write(&pool, &mut records).await;
+  }
+  info!(flushed, "Log writer stopped");
+}
+
+/// Inserts `records`, retrying with backoff up to `LOG_WRITE_ATTEMPTS` times. They were
+/// already accepted, so they are only dropped once every attempt failed.
+async fn write(pool: &PgPool, records: &mut Vec<LogToolCallRequest>) {
+  let mut delay = LOG_WRITE_RETRY_DELAY;
+
+  for attempt in 1..=LOG_WRITE_ATTEMPTS {
+    match insert_tool_calls(pool, records).await {
+      Ok(()) => break,
+      Err(e) if attempt == LOG_WRITE_ATTEMPTS => {
+        error!(dropped = records.len(), error = %e, "Failed to write logged tool calls");
+        record_dropped_tool_calls(records.len());
+      }
+      Err(e) => {
+        warn!(attempt, records = records.len(), error = %e, "Failed to write logged tool calls, retrying");
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+      }
+    }
+  }
+
+  records.clear();
+}
@@ -1,68 +1,244 @@
+mod batches;
+mod collections;
+mod embedding_migration;
+mod embedding_template;
 mod embeddings;
+mod health;
 mod heartbeat;
+mod log_writer;
+mod memory_store;
 mod metrics;
+mod pgvector_store;
+mod ping_history;
+mod probes;
+mod prometheus_metrics;
+mod qdrant_store;
+mod reconnect;
+mod routing_audit;
+mod telemetry;
+mod tool_call_rollups;
 mod tool_metrics;
+mod tool_prices;
 mod tool_registration;
 mod tool_retrieval;
+mod tool_stats;
 mod types;
 mod utils;
+mod vector_store;
 
 use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use anyhow::Result;
 use axum::{
   Router,
-  routing::{get, post},
+  routing::{delete, get, post},
 };
+use chrono::TimeDelta;
 use qdrant_client::Qdrant;
 use sqlx::PgPool;
 use tokio::sync::RwLock;
+use tracing::{error, info, warn};
 
 use crate::{
+  batches::{renew_batch, restore_batches},
+  embedding_migration::migrate_embeddings,
   heartbeat::heartbeat_service,
+  log_writer::LogWriter,
+  memory_store::MemoryVectorStore,
   metrics::post_metrics,
-  tool_metrics::log_tool_call,
+  pgvector_store::PgVectorStore,
+  probes::{delete_probe, list_probes, put_probe},
+  prometheus_metrics::{get_prometheus_metrics, install_recorder},
+  qdrant_store::QdrantVectorStore,
+  routing_audit::list_routing_decisions,
+  telemetry::init_telemetry,
+  tool_call_rollups::start_maintenance,
+  tool_metrics::{LogValidation, UnknownToolPolicy, log_tool_call, log_tool_calls},
+  tool_prices::{delete_price, list_prices, put_price},
   tool_registration::{register_server, unregister_server},
   tool_retrieval::search_tools,
+  tool_stats::get_tool_stats,
   types::{AppData, AppState},
+  vector_store::VectorStore,
 };
 
 const MAX_PING_HISTORY: usize = 100;
 const HEARTBEAT_INTERVAL_SECONDS: u64 = 10;
+/// Consecutive failed pings after which a server counts as down.
+const DOWN_AFTER_FAILURES: u32 = 3;
+/// Delay before retrying a failed reconnect, doubled after every further failure.
+const RECONNECT_BACKOFF_BASE: Duration = Duration::from_secs(HEARTBEAT_INTERVAL_SECONDS);
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(5 * 60);
+/// Default idle time after which a batch expires, unless its registration sets a TTL.
 const TIMEOUT_DURATION: Duration = Duration::from_secs(10 * 60);
+const MAX_BATCH_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// Searches write a batch's activity through to Postgres at most once per this fraction of
+/// its TTL, so a restarted scheduler may see a busy batch idle for that much longer.
+const ACTIVITY_PERSIST_TTL_DIVISOR: u32 = 10;
 const QDRANT_URL: &str = dotenvy_macro::dotenv!("QDRANT_URL");
 pub const OPENROUTER_API_KEY: &str = dotenvy_macro::dotenv!("OPENROUTER_API_KEY");
 pub const DATABASE_URL: &str = dotenvy_macro::dotenv!("DATABASE_URL");
-pub const QDRANT_COLLECTION_NAME: &str = "mcp_tools";
-// pub const DEFAULT_TOOL_LIMIT: usize = 10;
-// pub const DEFAULT_MIN_SIMILARITY: f32 = 0.7;
+pub const TOOL_COLLECTION_NAME: &str = "mcp_tools";
+pub const DEFAULT_TOOL_LIMIT: usize = 10;
+pub const DEFAULT_MIN_SIMILARITY: f32 = 0.7;
 pub const CLUSTER_SIMILARITY_THRESHOLD: f32 = 0.75;
+pub const NAME_VECTOR_WEIGHT: f32 = 0.2;
+pub const DESCRIPTION_VECTOR_WEIGHT: f32 = 0.6;
+pub const SCHEMA_VECTOR_WEIGHT: f32 = 0.2;
 pub const MAX_TOOL_CALL_LOGS: i64 = 3;
+/// Most argument features a logged call or an input-aware search may carry.
+pub const MAX_TOOL_CALL_FEATURES: usize = 8;
+/// Recent successful calls per feature that input-aware routing averages.
+pub const FEATURE_LATENCY_SAMPLES: i64 = 50;
+/// Calls sharing a feature needed before input-aware routing trusts their mean over the
+/// tool's overall latency.
+pub const MIN_FEATURE_SAMPLES: i64 = 5;
+/// Most records the log writer inserts per statement.
+pub const LOG_BATCH_CHUNK_SIZE: usize = 1000;
+/// Logged tool calls waiting for the log writer. Once full, `/log` waits up to
+/// `LOG_ENQUEUE_TIMEOUT` for room and then answers 503.
+pub const LOG_QUEUE_CAPACITY: usize = 10_000;
+pub const LOG_ENQUEUE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Inserts the log writer attempts before dropping a chunk. While it retries, new records
+/// wait in the queue, so a database outage ends in 503s rather than unbounded memory.
+pub const LOG_WRITE_ATTEMPTS: u32 = 6;
+/// Wait before the first retry of a failed insert; doubled after each further failure.
+pub const LOG_WRITE_RETRY_DELAY: Duration = Duration::from_millis(250);
+/// Limit of JSON array bodies of `POST /log/batch`. NDJSON bodies are streamed, so only their
+/// lines are limited, by `MAX_NDJSON_LINE_BYTES`.
+pub const LOG_BATCH_MAX_BODY_BYTES: usize = 16 * 1024 * 1024;
+/// Longest record accepted in an NDJSON body of `POST /log/batch`; longer lines end the
+/// request with a 413.
+pub const MAX_NDJSON_LINE_BYTES: usize = 1024 * 1024;
+/// Number of recent pings averaged into a server's current round trip.
+pub const RTT_SAMPLE_COUNT: usize = 3;
+/// Share of the increase of a server's current round trip over its usual one added to its
+/// tools' call latency when routing. 0 ranks tools by logged call latency alone.
+pub const DEFAULT_RTT_WEIGHT: f64 = 1.0;
+/// Milliseconds of latency one expected output token is worth when routing. 0 ignores
+/// output size.
+pub const DEFAULT_OUTPUT_TOKEN_WEIGHT: f64 = 0.0;
+/// Bytes of tool output per LLM token, for calls that only report their output in bytes.
+pub const BYTES_PER_TOKEN: f64 = 4.0;
+/// Recent successful calls whose output sizes are averaged into a tool's expected output.
+pub const OUTPUT_SIZE_SAMPLES: i64 = 20;
+/// Recent calls, failed ones included, that deadline routing estimates a tool's chance of
+/// finishing in time from.
+pub const DEADLINE_SAMPLES: i64 = 100;
 pub const DEFAULT_EMBEDDING_MODEL: &str = "text-embedding-3-small";
+/// Overridden at runtime by the `EMBEDDING_TEMPLATE` environment variable.
+/// See `embedding_template::render_template` for the syntax.
+pub const DEFAULT_EMBEDDING_TEMPLATE: &str = "{name}[: {description}]";
 pub const OPENROUTER_EMBEDDINGS_URL: &str = "https://openrouter.ai/api/v1/embeddings";
+/// Used when a probe definition does not set `interval_seconds`. Probes run on heartbeat
+/// ticks, so intervals shorter than `HEARTBEAT_INTERVAL_SECONDS` behave like it.
+pub const DEFAULT_PROBE_INTERVAL_SECONDS: u32 = 60;
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_ROUTING_DECISION_LIMIT: i64 = 100;
+pub const MAX_ROUTING_DECISION_LIMIT: i64 = 1000;
+/// Overridden at runtime by the `TOOL_CALL_RETENTION_DAYS` environment variable.
+pub const DEFAULT_TOOL_CALL_RETENTION_DAYS: i64 = 7;
+/// Hourly tool call rollups are kept this long, or as long as raw tool calls if that is longer;
+/// older windows are answered from daily ones.
+pub const HOURLY_ROLLUP_RETENTION_DAYS: i64 = 35;
+/// Raw heartbeat pings are kept this long, for inspection; history is served from rollups.
+pub const PING_RESULT_RETENTION_DAYS: i64 = 7;
+/// Per-minute ping rollups are kept this long; older ranges are answered from hourly ones.
+pub const MINUTE_PING_ROLLUP_RETENTION_DAYS: i64 = 7;
+/// Cron schedule, with seconds, of the tool call rollup and retention job. It also applies
+/// ping retention.
+pub const TOOL_CALL_MAINTENANCE_SCHEDULE: &str = "0 5 * * * *";
 pub const N_ERROR_THRESHOLD: i64 = 5;
 pub const M_ERROR_WINDOW_MINUTES: i64 = 10;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-  if cfg!(not(target_arch = "wasm32")) {
-    tracing_subscriber::fmt::init();
-  }
+  dotenvy::dotenv().ok();
+
+  let telemetry = init_telemetry()?;
+
+  let metrics_handle = install_recorder()?;
 
   let pool = PgPool::connect(DATABASE_URL).await?;
 
   sqlx::migrate!("./migrations").run(&pool).await?;
-  println!("Database migrations completed");
+  info!("Database migrations completed");
+
+  let vector_store: Arc<dyn VectorStore> = match std::env::var("VECTOR_STORE").as_deref() {
+    Ok("memory") => {
+      info!("Using in-memory vector store");
+      Arc::new(MemoryVectorStore::new())
+    }
+    Ok("pgvector") => {
+      info!("Using pgvector store");
+      Arc::new(PgVectorStore::connect(pool.clone()).await?)
+    }
+    _ => Arc::new(QdrantVectorStore::new(Qdrant::from_url(QDRANT_URL).build().unwrap())),
+  };
+
+  let embedding_model = match vector_store.active_embedding_model().await {
+    Ok(Some(model)) => {
+      if model != DEFAULT_EMBEDDING_MODEL {
+        warn!(
+          active_model = %model,
+          default_model = DEFAULT_EMBEDDING_MODEL,
+          "Active collection was embedded with another model than the default; it stays in use until an embedding migration"
+        );
+      }
+      model
+    }
+    Ok(None) => DEFAULT_EMBEDDING_MODEL.to_string(),
+    Err(e) => {
+      warn!(error = %e, "Failed to read active embedding model, using {}", DEFAULT_EMBEDDING_MODEL);
+      DEFAULT_EMBEDDING_MODEL.to_string()
+    }
+  };
+  info!(embedding_model = %embedding_model, "Using embedding model");
 
-  let qdrant_client = Arc::new(Qdrant::from_url(QDRANT_URL).build().unwrap());
+  let embedding_template = std::env::var("EMBEDDING_TEMPLATE").unwrap_or_else(|_| DEFAULT_EMBEDDING_TEMPLATE.to_string());
+  info!(embedding_template = %embedding_template, "Using embedding template");
+
+  let tool_call_retention = std::env::var("TOOL_CALL_RETENTION_DAYS")
+    .ok()
+    .and_then(|days| days.parse().ok())
+    .map(TimeDelta::days)
+    .unwrap_or(TimeDelta::days(DEFAULT_TOOL_CALL_RETENTION_DAYS));
+  info!(retention_days = tool_call_retention.num_days(), "Keeping raw tool calls");
+
+  let mut maintenance = start_maintenance(pool.clone(), tool_call_retention).await?;
+
+  let (log_writer, log_writer_handle) = LogWriter::spawn(pool.clone());
+
+  let log_validation = LogValidation {
+    unknown_tools: match std::env::var("LOG_UNKNOWN_TOOLS").as_deref() {
+      Ok("reject") => UnknownToolPolicy::Reject,
+      _ => UnknownToolPolicy::Flag,
+    },
+    require_batch_id: std::env::var("LOG_REQUIRE_BATCH_ID").is_ok_and(|value| value == "true" || value == "1"),
+  };
+  info!(
+    unknown_tools = ?log_validation.unknown_tools,
+    require_batch_id = log_validation.require_batch_id,
+    "Validating logged tool calls"
+  );
 
   let state = AppState::new(RwLock::new(AppData {
     servers: HashMap::new(),
     batch_map: HashMap::new(),
-    qdrant: qdrant_client,
+    vector_store,
+    embedding_model,
+    embedding_template,
     pool,
+    metrics_handle,
+    tool_call_retention,
+    log_writer,
+    log_validation,
   }));
 
+  if let Err(e) = restore_batches(&state).await {
+    error!(error = %e, "Failed to restore batches");
+  }
+
   let heartbeat_state = state.clone();
 
   tokio::spawn(async move {
@@ -73,14 +249,30 @@ async fn main() -> Result<()> {
     .route("/", get(root))
     .route("/register", post(register_server))
     .route("/unregister", post(unregister_server))
+    .route("/batches/{id}/renew", post(renew_batch))
     .route("/metrics", post(post_metrics))
+    .route("/metrics/prometheus", get(get_prometheus_metrics))
     .route("/search", get(search_tools))
+    .route("/routing/decisions", get(list_routing_decisions))
     .route("/log", post(log_tool_call))
+    .route("/log/batch", post(log_tool_calls))
+    .route("/stats/tools", get(get_tool_stats))
+    .route("/admin/embeddings/migrate", post(migrate_embeddings))
+    .route("/probes", get(list_probes).post(put_probe))
+    .route("/probes/{id}", delete(delete_probe))
+    .route("/prices", get(list_prices).post(put_price))
+    .route("/prices/{id}", delete(delete_price))
     .with_state(state);
 
   let listener = tokio::net::TcpListener::bind("0.0.0.0:4000").await.unwrap();
-  println!("Axum server listening on 0.0.0.0:4000");
-  axum::serve(listener, app).await.unwrap();
+  info!("Axum server listening on 0.0.0.0:4000");
+  axum::serve(listener, app).with_graceful_shutdown(shutdown_signal()).await.unwrap();
+
+  info!("Shutting down");
+  log_writer_handle.flush_and_stop().await;
+
+  maintenance.shutdown().await?;
+  telemetry.shutdown();
 
   Ok(())
 }
@@ -88,3 +280,27 @@ async fn main() -> Result<()> {
 async fn root() -> &'static str {
   "MCP Heartbeat Monitor Running"
 }
+
+/// Resolves on Ctrl+C or SIGTERM, letting in-flight requests finish before main flushes the
+/// log writer.
+async fn shutdown_signal() {
+  let ctrl_c = async {
+    tokio::signal::ctrl_c().await.expect("failed to install Ctrl+C handler");
+  };
+
+  #[cfg(unix)]
+  let terminate = async {
+    tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+      .expect("failed to install SIGTERM handler")
+      .recv()
+      .await;
+  };
+
+  #[cfg(not(unix))]
+  let terminate = std::future::pending::<()>();
+
+  tokio::select! {
+    _ = ctrl_c => {},
+    _ = terminate => {},
+  }
+}
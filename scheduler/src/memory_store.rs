@@ -0,0 +1,273 @@
+use std::{
+  collections::{BTreeMap, HashMap},
+  sync::RwLock,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::{
+  TOOL_COLLECTION_NAME,
+  collections::collection_name_for,
+  vector_store::{PayloadFilter, ToolPoint, VectorStore, cosine_similarity},
+};
+
+/// Brute-force vector store kept entirely in process memory. Nothing survives a restart,
+/// which makes it suitable for tests and small single-node deployments without Qdrant.
+#[derive(Default)]
+pub(crate) struct MemoryVectorStore {
+  state: RwLock<MemoryState>,
+}
+
+#[derive(Default)]
+struct MemoryState {
+  collections: HashMap<String, MemoryCollection>,
+  active: Option<String>,
+}
+
+struct MemoryCollection {
+  model: String,
+  vector_size: u64,
+  points: BTreeMap<String, ToolPoint>,
+}
+
+impl MemoryState {
+  fn resolve(&self, collection: &str) -> Option<&MemoryCollection> {
+    let name = if collection == TOOL_COLLECTION_NAME {
+      self.active.as_deref()?
+    } else {
+      collection
+    };
+    self.collections.get(name)
+  }
+
+  fn resolve_mut(&mut self, collection: &str) -> Result<&mut MemoryCollection> {
+    let name = if collection == TOOL_COLLECTION_NAME {
+      self.active.clone().ok_or_else(|| anyhow::anyhow!("No active collection"))?
+    } else {
+      collection.to_string()
+    };
+    self
+      .collections
+      .get_mut(&name)
+      .ok_or_else(|| anyhow::anyhow!("Collection {} does not exist", name))
+  }
+}
+
+impl MemoryVectorStore {
+  pub(crate) fn new() -> Self {
+    Self::default()
+  }
+}
+
+#[async_trait]
+impl VectorStore for MemoryVectorStore {
+  async fn active_collection(&self) -> Result<Option<String>> {
+    Ok(self.state.read().unwrap().active.clone())
+  }
+
+  async fn active_embedding_model(&self) -> Result<Option<String>> {
+    let state = self.state.read().unwrap();
+    Ok(state.resolve(TOOL_COLLECTION_NAME).map(|c| c.model.clone()))
+  }
+
+  async fn create_collection(&self, model: &str, vector_size: u64) -> Result<String> {
+    let collection_name = collection_name_for(model, vector_size);
+    let mut state = self.state.write().unwrap();
+
+    state
+      .collections
+      .entry(collection_name.clone())
+      .or_insert_with(|| MemoryCollection {
+        model: model.to_string(),
+        vector_size,
+        points: BTreeMap::new(),
+      });
+
+    Ok(collection_name)
+  }
+
+  async fn ensure_collection(&self, model: &str, vector_size: u64) -> Result<()> {
+    if let Some(collection) = self.state.read().unwrap().resolve(TOOL_COLLECTION_NAME) {
+      if collection.vector_size != vector_size {
+        anyhow::bail!(
+          "Active collection has vector size {} but model {} produces {}; run an embedding migration",
+          collection.vector_size,
+          model,
+          vector_size
+        );
+      }
+      return Ok(());
+    }
+
+    let collection = self.create_collection(model, vector_size).await?;
+    self.activate_collection(&collection).await
+  }
+
+  async fn activate_collection(&self, collection: &str) -> Result<()> {
+    let mut state = self.state.write().unwrap();
+    if !state.collections.contains_key(collection) {
+      anyhow::bail!("Collection {} does not exist", collection);
+    }
+    state.active = Some(collection.to_string());
+    Ok(())
+  }
+
+  async fn upsert(&self, collection: &str, points: Vec<ToolPoint>) -> Result<()> {
+    let mut state = self.state.write().unwrap();
+    let collection = state.resolve_mut(collection)?;
+    for point in points {
+      collection.points.insert(point.id.clone(), point);
+    }
+    Ok(())
+  }
+
+  async fn delete(&self, collection: &str, ids: Vec<String>) -> Result<()> {
+    let mut state = self.state.write().unwrap();
+    let collection = state.resolve_mut(collection)?;
+    for id in ids {
+      collection.points.remove(&id);
+    }
+    Ok(())
+  }
+
+  async fn scroll(&self, collection: &str, filter: Option<&PayloadFilter>, with_vectors: bool) -> Result<Vec<ToolPoint>> {
+    let state = self.state.read().unwrap();
+    let Some(collection) = state.resolve(collection) else {
+      return Ok(Vec::new());
+    };
+
+    Ok(
+      collection
+        .points
+        .values()
+        .filter(|point| filter.is_none_or(|f| f.accepts(&point.payload)))
+        .map(|point| {
+          let mut point = point.clone();
+          if !with_vectors {
+            point.vectors.clear();
+          }
+          point
+        })
+        .collect(),
+    )
+  }
+
+  async fn search(
+    &self,
+    collection: &str,
+    vector_name: &str,
+    query: &[f32],
+    filter: Option<&PayloadFilter>,
+    limit: usize,
+  ) -> Result<Vec<(ToolPoint, f32)>> {
+    let state = self.state.read().unwrap();
+    let Some(collection) = state.resolve(collection) else {
+      return Ok(Vec::new());
+    };
+
+    let mut scored: Vec<(ToolPoint, f32)> = collection
+      .points
+      .values()
+      .filter(|point| filter.is_none_or(|f| f.accepts(&point.payload)))
+      .filter_map(|point| {
+        point
+          .vectors
+          .get(vector_name)
+          .map(|vector| (point.clone(), cosine_similarity(query, vector)))
+      })
+      .collect();
+
+    scored.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    scored.truncate(limit);
+
+    Ok(scored)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::collections::{DESCRIPTION_VECTOR, NAME_VECTOR};
+
+  fn point(id: &str, mcp_url: &str, description: [f32; 2]) -> ToolPoint {
+    ToolPoint {
+      id: id.to_string(),
+      vectors: [(DESCRIPTION_VECTOR.to_string(), description.to_vec())].into(),
+      payload: [("mcp_url".to_string(), mcp_url.to_string())].into(),
+    }
+  }
+
+  /// A store whose active collection holds `a` and `b` on one server and `c` on another.
+  async fn store() -> MemoryVectorStore {
+    let store = MemoryVectorStore::new();
+    store.ensure_collection("model", 2).await.unwrap();
+    store
+      .upsert(
+        TOOL_COLLECTION_NAME,
+        vec![
+          point("a", "http://one", [1.0, 0.0]),
+          point("b", "http://one", [0.6, 0.8]),
+          point("c", "http://two", [0.0, 1.0]),
+        ],
+      )
+      .await
+      .unwrap();
+    store
+  }
+
+  fn ids<'a>(points: impl IntoIterator<Item = &'a ToolPoint>) -> Vec<&'a str> {
+    points.into_iter().map(|point| point.id.as_str()).collect()
+  }
+
+  #[tokio::test]
+  async fn search_cases() {
+    let store = store().await;
+    let one = PayloadFilter::matches("mcp_url", ["http://one".to_string()]);
+
+    let cases = [
+      ("ranked by similarity", DESCRIPTION_VECTOR, None, 3, vec!["a", "b", "c"]),
+      ("limited", DESCRIPTION_VECTOR, None, 1, vec!["a"]),
+      ("filtered", DESCRIPTION_VECTOR, Some(&one), 3, vec!["a", "b"]),
+      ("vector no point has", NAME_VECTOR, None, 3, vec![]),
+    ];
+
+    for (name, vector_name, filter, limit, expected) in cases {
+      let results = store
+        .search(TOOL_COLLECTION_NAME, vector_name, &[1.0, 0.0], filter, limit)
+        .await
+        .unwrap();
+      assert_eq!(ids(results.iter().map(|(point, _)| point)), expected, "{name}");
+    }
+  }
+
+  #[tokio::test]
+  async fn scroll_filters_and_strips_vectors() {
+    let store = store().await;
+    let two = PayloadFilter::matches("mcp_url", ["http://two".to_string()]);
+
+    let all = store.scroll(TOOL_COLLECTION_NAME, None, true).await.unwrap();
+    assert_eq!(ids(&all), ["a", "b", "c"]);
+    assert!(all.iter().all(|point| point.vectors.contains_key(DESCRIPTION_VECTOR)));
+
+    let filtered = store.scroll(TOOL_COLLECTION_NAME, Some(&two), false).await.unwrap();
+    assert_eq!(ids(&filtered), ["c"]);
+    assert!(filtered[0].vectors.is_empty());
+
+    assert!(store.scroll("missing", None, false).await.unwrap().is_empty());
+  }
+
+  #[tokio::test]
+  async fn delete_removes_points() {
+    let store = store().await;
+
+    store
+      .delete(TOOL_COLLECTION_NAME, vec!["a".to_string(), "missing".to_string()])
+      .await
+      .unwrap();
+
+    let remaining = store.scroll(TOOL_COLLECTION_NAME, None, false).await.unwrap();
+    assert_eq!(ids(&remaining), ["b", "c"]);
+    assert!(store.delete("missing", vec!["b".to_string()]).await.is_err());
+  }
+}
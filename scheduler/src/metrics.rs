@@ -1,12 +1,22 @@
-use axum::{Json, extract::State};
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use chrono::Utc;
+use tracing::{error, instrument};
 
 use crate::{
+  ping_history::ping_history,
   types::{AppState, BatchMetricsRequest, MetricResult},
   utils::calculate_average_latency,
 };
 
-pub(crate) async fn post_metrics(State(state): State<AppState>, Json(payload): Json<BatchMetricsRequest>) -> Json<Vec<MetricResult>> {
+#[instrument(skip_all, fields(mcp_urls = ?payload.mcp_urls))]
+pub(crate) async fn post_metrics(State(state): State<AppState>, Json(payload): Json<BatchMetricsRequest>) -> impl IntoResponse {
+  let range = payload.from.map(|from| (from, payload.to.unwrap_or_else(Utc::now)));
+  if range.is_some_and(|(from, to)| from > to) {
+    return (StatusCode::BAD_REQUEST, Json(Vec::<MetricResult>::new())).into_response();
+  }
+
   let app_data = state.read().await;
+  let pool = app_data.pool.clone();
   let mut results = Vec::new();
 
   for url in payload.mcp_urls {
@@ -18,10 +28,28 @@ pub(crate) async fn post_metrics(State(state): State<AppState>, Json(payload): J
         url,
         average_latency_ms: None,
         sample_count: None,
+        health: None,
+        consecutive_failures: None,
+        last_success_seconds_ago: None,
+        failed_ping_count: None,
+        last_failure_seconds_ago: None,
+        last_ping_error: None,
+        history: None,
         error: Some("URL not currently monitored.".to_string()),
       });
     }
   }
 
-  Json(results)
+  drop(app_data);
+
+  if let Some((from, to)) = range {
+    for result in &mut results {
+      match ping_history(&pool, &result.url, from, to).await {
+        Ok(history) => result.history = Some(history),
+        Err(e) => error!(mcp_url = %result.url, error = %e, "Failed to load ping history"),
+      }
+    }
+  }
+
+  (StatusCode::OK, Json(results)).into_response()
 }
@@ -0,0 +1,352 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use pgvector::Vector;
+use sqlx::{PgPool, Row, postgres::PgRow, types::Json};
+use tracing::info;
+
+use crate::{
+  TOOL_COLLECTION_NAME,
+  collections::{DESCRIPTION_VECTOR, NAME_VECTOR, SCHEMA_VECTOR, collection_name_for},
+  vector_store::{Payload, PayloadFilter, ToolPoint, ToolVectors, VectorStore, VectorWeights},
+};
+
+/// Same as the pgvector part of `02_tool_embeddings.sql`.
+const SCHEMA: &str = r#"
+CREATE EXTENSION IF NOT EXISTS vector;
+
+CREATE TABLE IF NOT EXISTS tool_embeddings (
+    collection TEXT NOT NULL REFERENCES tool_embedding_collections(name) ON DELETE CASCADE,
+    id TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    name_embedding vector,
+    description_embedding vector,
+    schema_embedding vector,
+    PRIMARY KEY (collection, id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_tool_embeddings_mcp_url ON tool_embeddings(collection, (payload->>'mcp_url'));
+"#;
+
+/// Keeps tool embeddings in Postgres next to the call logs, using the pgvector extension.
+///
+/// Collections are rows of `tool_embedding_collections` and `TOOL_COLLECTION_NAME` is a row of
+/// `tool_embedding_aliases`. Each named vector has its own column of `tool_embeddings`, and
+/// both query search and the similarity comparisons behind clustering run in SQL.
+///
+/// The queries are checked at runtime rather than with `query!`, since the `vector` type only
+/// exists on servers with the extension installed.
+pub(crate) struct PgVectorStore {
+  pool: PgPool,
+}
+
+impl PgVectorStore {
+  /// Creates the extension and `tool_embeddings` unless they exist. The migrations skip them
+  /// on servers without pgvector, so they may be missing if it was installed afterwards.
+  pub(crate) async fn connect(pool: PgPool) -> Result<Self> {
+    sqlx::raw_sql(SCHEMA).execute(&pool).await.map_err(|e| {
+      anyhow::anyhow!(
+        "Failed to create tool_embeddings ({e}); the pgvector extension must be available on the database server \
+         and the scheduler's role allowed to create it"
+      )
+    })?;
+
+    Ok(Self { pool })
+  }
+
+  async fn resolve(&self, collection: &str) -> Result<Option<String>> {
+    if collection == TOOL_COLLECTION_NAME {
+      self.active_collection().await
+    } else {
+      Ok(Some(collection.to_string()))
+    }
+  }
+
+  async fn resolve_existing(&self, collection: &str) -> Result<String> {
+    self
+      .resolve(collection)
+      .await?
+      .ok_or_else(|| anyhow::anyhow!("No active collection"))
+  }
+}
+
+#[async_trait]
+impl VectorStore for PgVectorStore {
+  async fn active_collection(&self) -> Result<Option<String>> {
+    let collection = sqlx::query_scalar("SELECT collection FROM tool_embedding_aliases WHERE alias = $1")
+      .bind(TOOL_COLLECTION_NAME)
+      .fetch_optional(&self.pool)
+      .await?;
+
+    Ok(collection)
+  }
+
+  async fn active_embedding_model(&self) -> Result<Option<String>> {
+    let model = sqlx::query_scalar(
+      r#"
+      SELECT c.embedding_model
+      FROM tool_embedding_aliases a
+      JOIN tool_embedding_collections c ON c.name = a.collection
+      WHERE a.alias = $1
+      "#,
+    )
+    .bind(TOOL_COLLECTION_NAME)
+    .fetch_optional(&self.pool)
+    .await?;
+
+    Ok(model)
+  }
+
+  async fn create_collection(&self, model: &str, vector_size: u64) -> Result<String> {
+    let collection_name = collection_name_for(model, vector_size);
+
+    let created = sqlx::query(
+      r#"
+      INSERT INTO tool_embedding_collections (name, embedding_model, vector_size)
+      VALUES ($1, $2, $3)
+      ON CONFLICT (name) DO NOTHING
+      "#,
+    )
+    .bind(&collection_name)
+    .bind(model)
+    .bind(vector_size as i32)
+    .execute(&self.pool)
+    .await?
+    .rows_affected();
+
+    if created > 0 {
+      info!(collection = %collection_name, vector_size, "Created collection");
+    }
+
+    Ok(collection_name)
+  }
+
+  async fn ensure_collection(&self, model: &str, vector_size: u64) -> Result<()> {
+    if let Some(collection) = self.active_collection().await? {
+      let size: i32 = sqlx::query_scalar("SELECT vector_size FROM tool_embedding_collections WHERE name = $1")
+        .bind(&collection)
+        .fetch_one(&self.pool)
+        .await?;
+
+      if size as u64 != vector_size {
+        anyhow::bail!(
+          "Collection {} has vector size {} but model {} produces {}; run an embedding migration",
+          collection,
+          size,
+          model,
+          vector_size
+        );
+      }
+      return Ok(());
+    }
+
+    let collection = self.create_collection(model, vector_size).await?;
+    self.activate_collection(&collection).await
+  }
+
+  async fn activate_collection(&self, collection: &str) -> Result<()> {
+    sqlx::query(
+      r#"
+      INSERT INTO tool_embedding_aliases (alias, collection)
+      VALUES ($1, $2)
+      ON CONFLICT (alias) DO UPDATE SET collection = EXCLUDED.collection
+      "#,
+    )
+    .bind(TOOL_COLLECTION_NAME)
+    .bind(collection)
+    .execute(&self.pool)
+    .await?;
+    info!(alias = TOOL_COLLECTION_NAME, collection, "Alias now points at collection");
+
+    Ok(())
+  }
+
+  async fn upsert(&self, collection: &str, points: Vec<ToolPoint>) -> Result<()> {
+    let collection = self.resolve_existing(collection).await?;
+    let mut tx = self.pool.begin().await?;
+
+    for point in points {
+      let vector = |name: &str| point.vectors.get(name).map(|v| Vector::from(v.clone()));
+
+      sqlx::query(
+        r#"
+        INSERT INTO tool_embeddings (collection, id, payload, name_embedding, description_embedding, schema_embedding)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (collection, id) DO UPDATE SET
+          payload = EXCLUDED.payload,
+          name_embedding = EXCLUDED.name_embedding,
+          description_embedding = EXCLUDED.description_embedding,
+          schema_embedding = EXCLUDED.schema_embedding
+        "#,
+      )
+      .bind(&collection)
+      .bind(&point.id)
+      .bind(Json(&point.payload))
+      .bind(vector(NAME_VECTOR))
+      .bind(vector(DESCRIPTION_VECTOR))
+      .bind(vector(SCHEMA_VECTOR))
+      .execute(&mut *tx)
+      .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+  }
+
+  async fn delete(&self, collection: &str, ids: Vec<String>) -> Result<()> {
+    if ids.is_empty() {
+      return Ok(());
+    }
+
+    let collection = self.resolve_existing(collection).await?;
+
+    sqlx::query("DELETE FROM tool_embeddings WHERE collection = $1 AND id = ANY($2)")
+      .bind(&collection)
+      .bind(&ids)
+      .execute(&self.pool)
+      .await?;
+
+    Ok(())
+  }
+
+  async fn scroll(&self, collection: &str, filter: Option<&PayloadFilter>, with_vectors: bool) -> Result<Vec<ToolPoint>> {
+    let Some(collection) = self.resolve(collection).await? else {
+      return Ok(Vec::new());
+    };
+
+    let rows = sqlx::query(
+      r#"
+      SELECT
+        id,
+        payload,
+        CASE WHEN $4 THEN name_embedding END AS name_embedding,
+        CASE WHEN $4 THEN description_embedding END AS description_embedding,
+        CASE WHEN $4 THEN schema_embedding END AS schema_embedding
+      FROM tool_embeddings
+      WHERE collection = $1
+        AND ($2::TEXT IS NULL OR payload ->> $2 = ANY($3))
+      ORDER BY id
+      "#,
+    )
+    .bind(&collection)
+    .bind(filter.map(|f| f.key.as_str()))
+    .bind(filter.map(|f| f.any_of.clone()).unwrap_or_default())
+    .bind(with_vectors)
+    .fetch_all(&self.pool)
+    .await?;
+
+    rows.iter().map(to_tool_point).collect()
+  }
+
+  async fn search(
+    &self,
+    collection: &str,
+    vector_name: &str,
+    query: &[f32],
+    filter: Option<&PayloadFilter>,
+    limit: usize,
+  ) -> Result<Vec<(ToolPoint, f32)>> {
+    let Some(collection) = self.resolve(collection).await? else {
+      return Ok(Vec::new());
+    };
+    let column = vector_column(vector_name)?;
+
+    let rows = sqlx::query(&format!(
+      r#"
+      SELECT id, payload, name_embedding, description_embedding, schema_embedding, 1 - ({column} <=> $2) AS score
+      FROM tool_embeddings
+      WHERE collection = $1
+        AND {column} IS NOT NULL
+        AND ($3::TEXT IS NULL OR payload ->> $3 = ANY($4))
+      ORDER BY {column} <=> $2
+      LIMIT $5
+      "#
+    ))
+    .bind(&collection)
+    .bind(Vector::from(query.to_vec()))
+    .bind(filter.map(|f| f.key.as_str()))
+    .bind(filter.map(|f| f.any_of.clone()).unwrap_or_default())
+    .bind(limit as i64)
+    .fetch_all(&self.pool)
+    .await?;
+
+    rows
+      .iter()
+      .map(|row| Ok((to_tool_point(row)?, row.try_get::<f64, _>("score")? as f32)))
+      .collect()
+  }
+
+  /// The SQL counterpart of `weighted_similarity`: vectors missing on either side and
+  /// non-positive weights do not count towards the mean.
+  async fn similar_pairs(
+    &self,
+    collection: &str,
+    points: &[ToolPoint],
+    weights: VectorWeights,
+    threshold: f32,
+  ) -> Result<HashSet<(String, String)>> {
+    let Some(collection) = self.resolve(collection).await? else {
+      return Ok(HashSet::new());
+    };
+    let ids: Vec<String> = points.iter().map(|p| p.id.clone()).collect();
+
+    let pairs: Vec<(String, String)> = sqlx::query_as(
+      r#"
+      SELECT a.id, b.id
+      FROM tool_embeddings a
+      JOIN tool_embeddings b ON b.collection = a.collection AND b.id <> a.id
+      WHERE a.collection = $1
+        AND a.id = ANY($2)
+        AND b.id = ANY($2)
+        AND (
+          COALESCE($3 * (1 - (a.name_embedding <=> b.name_embedding)), 0)
+          + COALESCE($4 * (1 - (a.description_embedding <=> b.description_embedding)), 0)
+          + COALESCE($5 * (1 - (a.schema_embedding <=> b.schema_embedding)), 0)
+        ) / NULLIF(
+          CASE WHEN a.name_embedding IS NOT NULL AND b.name_embedding IS NOT NULL THEN $3 ELSE 0 END
+          + CASE WHEN a.description_embedding IS NOT NULL AND b.description_embedding IS NOT NULL THEN $4 ELSE 0 END
+          + CASE WHEN a.schema_embedding IS NOT NULL AND b.schema_embedding IS NOT NULL THEN $5 ELSE 0 END,
+          0
+        ) >= $6
+      "#,
+    )
+    .bind(&collection)
+    .bind(&ids)
+    .bind(weights.name.max(0.0) as f64)
+    .bind(weights.description.max(0.0) as f64)
+    .bind(weights.schema.max(0.0) as f64)
+    .bind(threshold as f64)
+    .fetch_all(&self.pool)
+    .await?;
+
+    Ok(pairs.into_iter().collect())
+  }
+}
+
+fn vector_column(vector_name: &str) -> Result<&'static str> {
+  match vector_name {
+    NAME_VECTOR => Ok("name_embedding"),
+    DESCRIPTION_VECTOR => Ok("description_embedding"),
+    SCHEMA_VECTOR => Ok("schema_embedding"),
+    other => anyhow::bail!("Unknown vector {}", other),
+  }
+}
+
+fn to_tool_point(row: &PgRow) -> Result<ToolPoint> {
+  let Json(payload): Json<Payload> = row.try_get("payload")?;
+
+  let mut vectors = ToolVectors::new();
+  for name in [NAME_VECTOR, DESCRIPTION_VECTOR, SCHEMA_VECTOR] {
+    if let Some(vector) = row.try_get::<Option<Vector>, _>(vector_column(name)?)? {
+      vectors.insert(name.to_string(), vector.to_vec());
+    }
+  }
+
+  Ok(ToolPoint {
+    id: row.try_get("id")?,
+    vectors,
+    payload,
+  })
+}
@@ -0,0 +1,175 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+use tracing::{error, info, instrument};
+
+use crate::{MINUTE_PING_ROLLUP_RETENTION_DAYS, PING_RESULT_RETENTION_DAYS};
+
+/// Bucket sizes, in seconds, of the rollups kept in `ping_rollups`.
+const ROLLUP_RESOLUTIONS: [i32; 2] = [60, 60 * 60];
+
+/// Ranges longer than this, or reaching past `MINUTE_PING_ROLLUP_RETENTION_DAYS`, are answered
+/// from hourly instead of per-minute rollups.
+const MINUTE_ROLLUP_MAX_RANGE: TimeDelta = TimeDelta::hours(24);
+
+#[derive(Serialize)]
+pub(crate) struct PingBucket {
+  pub(crate) start: DateTime<Utc>,
+  pub(crate) ping_count: i64,
+  pub(crate) availability: f64,
+  pub(crate) average_latency_ms: Option<f64>,
+  pub(crate) min_latency_ms: Option<f64>,
+  pub(crate) max_latency_ms: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct PingHistory {
+  pub(crate) from: DateTime<Utc>,
+  pub(crate) to: DateTime<Utc>,
+  pub(crate) bucket_seconds: i32,
+  pub(crate) ping_count: i64,
+  pub(crate) availability: Option<f64>,
+  pub(crate) average_latency_ms: Option<f64>,
+  pub(crate) buckets: Vec<PingBucket>,
+}
+
+/// Stores a heartbeat ping and folds it into every rollup resolution.
+pub(crate) async fn record_ping(pool: &PgPool, mcp_url: &str, latency: Duration, error: Option<String>) -> Result<()> {
+  let latency_ms = latency.as_secs_f64() * 1000.0;
+  let is_success = error.is_none();
+  let mut tx = pool.begin().await?;
+
+  sqlx::query!(
+    r#"
+    INSERT INTO ping_results (mcp_url, latency_ms, is_success, error)
+    VALUES ($1, $2, $3, $4)
+    "#,
+    mcp_url,
+    latency_ms,
+    is_success,
+    error
+  )
+  .execute(&mut *tx)
+  .await?;
+
+  sqlx::query!(
+    r#"
+    INSERT INTO ping_rollups (
+      mcp_url, bucket_seconds, bucket_start, ping_count, success_count, latency_sum_ms, min_latency_ms, max_latency_ms
+    )
+    SELECT
+      $1,
+      resolution,
+      TO_TIMESTAMP(FLOOR(EXTRACT(EPOCH FROM NOW()) / resolution) * resolution),
+      1,
+      CASE WHEN $3 THEN 1 ELSE 0 END,
+      CASE WHEN $3 THEN $2::FLOAT8 ELSE 0 END,
+      CASE WHEN $3 THEN $2::FLOAT8 END,
+      CASE WHEN $3 THEN $2::FLOAT8 END
+    FROM UNNEST($4::INTEGER[]) AS resolution
+    ON CONFLICT (mcp_url, bucket_seconds, bucket_start) DO UPDATE SET
+      ping_count = ping_rollups.ping_count + EXCLUDED.ping_count,
+      success_count = ping_rollups.success_count + EXCLUDED.success_count,
+      latency_sum_ms = ping_rollups.latency_sum_ms + EXCLUDED.latency_sum_ms,
+      min_latency_ms = LEAST(ping_rollups.min_latency_ms, EXCLUDED.min_latency_ms),
+      max_latency_ms = GREATEST(ping_rollups.max_latency_ms, EXCLUDED.max_latency_ms)
+    "#,
+    mcp_url,
+    latency_ms,
+    is_success,
+    &ROLLUP_RESOLUTIONS
+  )
+  .execute(&mut *tx)
+  .await?;
+
+  tx.commit().await?;
+
+  Ok(())
+}
+
+/// Ping latency and availability of `mcp_url` between `from` and `to`, from the rollups.
+/// Buckets overlapping either end of the range are included whole.
+pub(crate) async fn ping_history(pool: &PgPool, mcp_url: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<PingHistory> {
+  let minutes_retained_from = Utc::now() - TimeDelta::days(MINUTE_PING_ROLLUP_RETENTION_DAYS);
+  let bucket_seconds = if to - from <= MINUTE_ROLLUP_MAX_RANGE && from >= minutes_retained_from {
+    ROLLUP_RESOLUTIONS[0]
+  } else {
+    ROLLUP_RESOLUTIONS[1]
+  };
+
+  let rows = sqlx::query!(
+    r#"
+    SELECT bucket_start, ping_count, success_count, latency_sum_ms, min_latency_ms, max_latency_ms
+    FROM ping_rollups
+    WHERE mcp_url = $1
+      AND bucket_seconds = $2
+      AND bucket_start > $3::TIMESTAMPTZ - $2 * INTERVAL '1 second'
+      AND bucket_start <= $4
+    ORDER BY bucket_start
+    "#,
+    mcp_url,
+    bucket_seconds,
+    from,
+    to
+  )
+  .fetch_all(pool)
+  .await?;
+
+  let ping_count: i64 = rows.iter().map(|r| r.ping_count).sum();
+  let success_count: i64 = rows.iter().map(|r| r.success_count).sum();
+  let latency_sum_ms: f64 = rows.iter().map(|r| r.latency_sum_ms).sum();
+
+  let buckets = rows
+    .into_iter()
+    .map(|row| PingBucket {
+      start: row.bucket_start,
+      ping_count: row.ping_count,
+      availability: row.success_count as f64 / row.ping_count as f64,
+      average_latency_ms: (row.success_count > 0).then(|| row.latency_sum_ms / row.success_count as f64),
+      min_latency_ms: row.min_latency_ms,
+      max_latency_ms: row.max_latency_ms,
+    })
+    .collect();
+
+  Ok(PingHistory {
+    from,
+    to,
+    bucket_seconds,
+    ping_count,
+    availability: (ping_count > 0).then(|| success_count as f64 / ping_count as f64),
+    average_latency_ms: (success_count > 0).then(|| latency_sum_ms / success_count as f64),
+    buckets,
+  })
+}
+
+/// Deletes raw pings older than `PING_RESULT_RETENTION_DAYS` and per-minute rollups older than
+/// `MINUTE_PING_ROLLUP_RETENTION_DAYS`. Hourly rollups are kept.
+#[instrument(skip_all)]
+pub(crate) async fn apply_retention(pool: &PgPool) {
+  let result = sqlx::query!(
+    "DELETE FROM ping_results WHERE timestamp < $1",
+    Utc::now() - TimeDelta::days(PING_RESULT_RETENTION_DAYS)
+  )
+  .execute(pool)
+  .await;
+
+  match result {
+    Ok(result) => info!(deleted = result.rows_affected(), "Applied ping retention"),
+    Err(e) => error!(error = %e, "Failed to delete expired pings"),
+  }
+
+  let result = sqlx::query!(
+    "DELETE FROM ping_rollups WHERE bucket_seconds = $1 AND bucket_start < $2",
+    ROLLUP_RESOLUTIONS[0],
+    Utc::now() - TimeDelta::days(MINUTE_PING_ROLLUP_RETENTION_DAYS)
+  )
+  .execute(pool)
+  .await;
+
+  if let Err(e) = result {
+    error!(error = %e, "Failed to delete expired minute ping rollups");
+  }
+}
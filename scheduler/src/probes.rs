@@ -0,0 +1,283 @@
+use std::time::Instant;
+
+use axum::{
+  Json,
+  extract::{Path, State},
+  http::StatusCode,
+  response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use rmcp::{
+  ServiceError,
+  model::{CallToolRequestParam, CallToolResult, ErrorCode, JsonObject},
+};
+use serde::{Deserialize, Serialize};
+use tracing::{Instrument, error, info, info_span, instrument, warn};
+use uuid::Uuid;
+
+use crate::{
+  DEFAULT_PROBE_INTERVAL_SECONDS, PROBE_TIMEOUT,
+  prometheus_metrics::record_tool_call,
+  tool_metrics::ErrorCategory,
+  tool_prices::load_prices,
+  types::{AppState, DynamicMcpClient},
+};
+
+/// `tool_call_results.source` of calls made by the heartbeat rather than reported by agents.
+const PROBE_SOURCE: &str = "probe";
+
+/// A sample call the heartbeat makes to a tool so its latency stays known while no agent
+/// uses it. The call succeeds when the tool does not report an error and, if
+/// `expect_output_contains` is set, its text output contains that string.
+#[derive(Deserialize)]
+pub(crate) struct ToolProbeRequest {
+  pub(crate) tool_name: String,
+  pub(crate) mcp_url: String,
+  pub(crate) arguments: Option<JsonObject>,
+  pub(crate) expect_output_contains: Option<String>,
+  pub(crate) interval_seconds: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct ToolProbe {
+  pub(crate) id: Uuid,
+  pub(crate) tool_name: String,
+  pub(crate) mcp_url: String,
+  pub(crate) arguments: serde_json::Value,
+  pub(crate) expect_output_contains: Option<String>,
+  pub(crate) interval_seconds: i32,
+  pub(crate) last_run_at: Option<DateTime<Utc>>,
+}
+
+/// Creates a probe, or replaces the one already defined for the same tool.
+#[instrument(skip_all, fields(tool_name = %payload.tool_name, mcp_url = %payload.mcp_url))]
+pub(crate) async fn put_probe(State(state): State<AppState>, Json(payload): Json<ToolProbeRequest>) -> impl IntoResponse {
+  let interval_seconds = match i32::try_from(payload.interval_seconds.unwrap_or(DEFAULT_PROBE_INTERVAL_SECONDS)) {
+    Ok(interval_seconds) if interval_seconds >= 1 => interval_seconds,
+    _ => return (StatusCode::BAD_REQUEST, "interval_seconds must be a positive 32-bit integer").into_response(),
+  };
+
+  let pool = state.read().await.pool.clone();
+  let arguments = serde_json::Value::Object(payload.arguments.unwrap_or_default());
+
+  let result = sqlx::query_as!(
+    ToolProbe,
+    r#"
+    INSERT INTO tool_probes (tool_name, mcp_url, arguments, expect_output_contains, interval_seconds)
+    VALUES ($1, $2, $3, $4, $5)
+    ON CONFLICT (tool_name, mcp_url) DO UPDATE SET
+      arguments = EXCLUDED.arguments,
+      expect_output_contains = EXCLUDED.expect_output_contains,
+      interval_seconds = EXCLUDED.interval_seconds,
+      last_run_at = NULL
+    RETURNING id, tool_name, mcp_url, arguments, expect_output_contains, interval_seconds, last_run_at
+    "#,
+    payload.tool_name,
+    payload.mcp_url,
+    arguments,
+    payload.expect_output_contains,
+    interval_seconds
+  )
+  .fetch_one(&pool)
+  .await;
+
+  match result {
+    Ok(probe) => {
+      info!(interval_seconds = probe.interval_seconds, "Probe saved");
+      (StatusCode::CREATED, Json(Some(probe))).into_response()
+    }
+    Err(e) => {
+      error!(error = %e, "Failed to save probe");
+      (StatusCode::INTERNAL_SERVER_ERROR, Json(None::<ToolProbe>)).into_response()
+    }
+  }
+}
+
+#[instrument(skip_all)]
+pub(crate) async fn list_probes(State(state): State<AppState>) -> impl IntoResponse {
+  let pool = state.read().await.pool.clone();
+
+  let result = sqlx::query_as!(
+    ToolProbe,
+    r#"
+    SELECT id, tool_name, mcp_url, arguments, expect_output_contains, interval_seconds, last_run_at
+    FROM tool_probes
+    ORDER BY mcp_url, tool_name
+    "#
+  )
+  .fetch_all(&pool)
+  .await;
+
+  match result {
+    Ok(probes) => (StatusCode::OK, Json(probes)).into_response(),
+    Err(e) => {
+      error!(error = %e, "Failed to list probes");
+      (StatusCode::INTERNAL_SERVER_ERROR, Json(Vec::<ToolProbe>::new())).into_response()
+    }
+  }
+}
+
+#[instrument(skip(state))]
+pub(crate) async fn delete_probe(State(state): State<AppState>, Path(id): Path<Uuid>) -> impl IntoResponse {
+  let pool = state.read().await.pool.clone();
+
+  match sqlx::query!("DELETE FROM tool_probes WHERE id = $1", id).execute(&pool).await {
+    Ok(result) if result.rows_affected() == 0 => StatusCode::NOT_FOUND,
+    Ok(_) => StatusCode::NO_CONTENT,
+    Err(e) => {
+      error!(error = %e, "Failed to delete probe");
+      StatusCode::INTERNAL_SERVER_ERROR
+    }
+  }
+}
+
+/// Runs every probe of `url` whose interval has elapsed and records the calls in
+/// `tool_call_results`. Probes are claimed by moving `last_run_at` before they run, so a slow
+/// probe is not started again by the next heartbeat. Probe calls are priced but belong to no
+/// batch, so they never use up a budget, and they do not count towards circuit breakers.
+#[instrument(skip(state, client), fields(mcp_url = %url))]
+pub(crate) async fn run_due_probes(state: AppState, url: String, client: DynamicMcpClient) {
+  let pool = state.read().await.pool.clone();
+
+  let due = sqlx::query!(
+    r#"
+    UPDATE tool_probes
+    SET last_run_at = NOW()
+    WHERE mcp_url = $1
+      AND (last_run_at IS NULL OR last_run_at <= NOW() - INTERVAL '1 second' * interval_seconds)
+    RETURNING tool_name, arguments, expect_output_contains
+    "#,
+    url
+  )
+  .fetch_all(&pool)
+  .await;
+
+  let due = match due {
+    Ok(due) => due,
+    Err(e) => {
+      error!(error = %e, "Failed to load probes");
+      return;
+    }
+  };
+
+  let prices = if due.is_empty() {
+    Default::default()
+  } else {
+    load_prices(&pool, std::slice::from_ref(&url)).await.unwrap_or_else(|e| {
+      warn!(error = %e, "Failed to load prices, recording probes unpriced");
+      Default::default()
+    })
+  };
+
+  for probe in due {
+    let span = info_span!("probe", tool_name = %probe.tool_name);
+    async {
+      let request = CallToolRequestParam {
+        name: probe.tool_name.clone().into(),
+        arguments: probe.arguments.as_object().cloned(),
+      };
+
+      let start_time = Instant::now();
+      let result = tokio::time::timeout(PROBE_TIMEOUT, client.call_tool(request)).await;
+      let duration = start_time.elapsed();
+
+      let failure = match result {
+        Err(_) => Some(ProbeFailure::new(
+          ErrorCategory::Timeout,
+          format!("timed out after {}s", PROBE_TIMEOUT.as_secs()),
+        )),
+        Ok(Err(e)) => Some(ProbeFailure::from_service_error(e)),
+        Ok(Ok(output)) => {
+          check_output(&output, probe.expect_output_contains.as_deref()).map(|reason| ProbeFailure::new(ErrorCategory::ToolError, reason))
+        }
+      };
+
+      record_tool_call(&probe.tool_name, &url, duration, failure.is_some(), PROBE_SOURCE);
+
+      match &failure {
+        None => info!(latency_ms = duration.as_secs_f64() * 1000.0, "Probe succeeded"),
+        Some(failure) => warn!(category = failure.category.as_str(), reason = %failure.reason, "Probe failed"),
+      }
+
+      let result = sqlx::query!(
+        r#"
+        INSERT INTO tool_call_results (
+          tool_name, mcp_url, total_time_ms, is_error, error_category, mcp_error_code, price, source
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+        probe.tool_name,
+        url,
+        duration.as_millis() as i64,
+        failure.is_some(),
+        failure.as_ref().map(|failure| failure.category.as_str()),
+        failure.as_ref().and_then(|failure| failure.mcp_error_code),
+        prices
+          .get(&url, &probe.tool_name)
+          .map(|price| price.of_call(duration.as_secs_f64() * 1000.0, None)),
+        PROBE_SOURCE
+      )
+      .execute(&pool)
+      .await;
+
+      if let Err(e) = result {
+        error!(error = %e, "Failed to record probe");
+      }
+    }
+    .instrument(span)
+    .await;
+  }
+}
+
+struct ProbeFailure {
+  category: ErrorCategory,
+  mcp_error_code: Option<i32>,
+  reason: String,
+}
+
+impl ProbeFailure {
+  fn new(category: ErrorCategory, reason: String) -> Self {
+    Self {
+      category,
+      mcp_error_code: None,
+      reason,
+    }
+  }
+
+  /// Errors the server answered with count as tool errors, or as validation errors when it
+  /// rejected the arguments. Everything else happened on the way there or back.
+  fn from_service_error(error: ServiceError) -> Self {
+    match error {
+      ServiceError::McpError(data) => Self {
+        category: if data.code == ErrorCode::INVALID_PARAMS {
+          ErrorCategory::Validation
+        } else {
+          ErrorCategory::ToolError
+        },
+        mcp_error_code: Some(data.code.0),
+        reason: data.message.to_string(),
+      },
+      ServiceError::Timeout { .. } => Self::new(ErrorCategory::Timeout, error.to_string()),
+      other => Self::new(ErrorCategory::Transport, format!("{:?}", other)),
+    }
+  }
+}
+
+/// Why `output` does not count as a successful probe, if it does not.
+fn check_output(output: &CallToolResult, expect_output_contains: Option<&str>) -> Option<String> {
+  if output.is_error == Some(true) {
+    return Some("tool reported an error".to_string());
+  }
+
+  let expected = expect_output_contains?;
+  let found = output
+    .content
+    .iter()
+    .any(|content| content.as_text().is_some_and(|text| text.text.contains(expected)));
+
+  if found {
+    None
+  } else {
+    Some(format!("output does not contain {:?}", expected))
+  }
+}
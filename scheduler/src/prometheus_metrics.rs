@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::{extract::State, http::header, response::IntoResponse};
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+use crate::types::AppState;
+
+/// Histogram buckets, in seconds, shared by ping and tool call latencies.
+const LATENCY_BUCKETS: [f64; 12] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Installs the global recorder the `record_*` functions report to. Its handle renders
+/// everything recorded so far for `GET /metrics/prometheus`.
+pub(crate) fn install_recorder() -> Result<PrometheusHandle> {
+  let handle = PrometheusBuilder::new()
+    .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), &LATENCY_BUCKETS)?
+    .install_recorder()?;
+
+  Ok(handle)
+}
+
+pub(crate) fn record_registration(server_count: usize) {
+  counter!("scheduler_registrations_total").increment(1);
+  counter!("scheduler_registered_servers_total").increment(server_count as u64);
+}
+
+pub(crate) fn record_ping(mcp_url: &str, duration: Duration, is_success: bool) {
+  let mcp_url = mcp_url.to_string();
+
+  if is_success {
+    histogram!("scheduler_ping_latency_seconds", "mcp_url" => mcp_url).record(duration.as_secs_f64());
+  } else {
+    counter!("scheduler_ping_failures_total", "mcp_url" => mcp_url).increment(1);
+  }
+}
+
+/// `source` tells calls reported by agents through `/log` apart from heartbeat probes.
+pub(crate) fn record_tool_call(tool_name: &str, mcp_url: &str, duration: Duration, is_error: bool, source: &'static str) {
+  let labels = [
+    ("tool_name", tool_name.to_string()),
+    ("mcp_url", mcp_url.to_string()),
+    ("source", source.to_string()),
+  ];
+
+  counter!("scheduler_tool_calls_total", &labels).increment(1);
+  histogram!("scheduler_tool_call_duration_seconds", &labels).record(duration.as_secs_f64());
+  if is_error {
+    counter!("scheduler_tool_call_errors_total", &labels).increment(1);
+  }
+}
+
+/// Logged tool calls the log writer gave up on after they were accepted.
+pub(crate) fn record_dropped_tool_calls(count: usize) {
+  counter!("scheduler_dropped_tool_calls_total").increment(count as u64);
+}
+
+pub(crate) fn record_search() {
+  counter!("scheduler_search_requests_total").increment(1);
+}
+
+pub(crate) fn record_selection(tool_name: &str, mcp_url: &str) {
+  counter!(
+    "scheduler_tool_selections_total",
+    "tool_name" => tool_name.to_string(),
+    "mcp_url" => mcp_url.to_string()
+  )
+  .increment(1);
+}
+
+/// Renders all metrics in the Prometheus text format. Gauges describing the current state are
+/// sampled here rather than maintained on every change.
+pub(crate) async fn get_prometheus_metrics(State(state): State<AppState>) -> impl IntoResponse {
+  let app_data = state.read().await;
+
+  gauge!("scheduler_active_batches").set(app_data.batch_map.len() as f64);
+  gauge!("scheduler_monitored_servers").set(app_data.servers.len() as f64);
+  gauge!("scheduler_log_queue_depth").set(app_data.log_writer.queued() as f64);
+
+  let body = app_data.metrics_handle.render();
+
+  ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
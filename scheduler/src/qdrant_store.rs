@@ -0,0 +1,398 @@
+use std::{
+  collections::{HashMap, HashSet},
+  sync::RwLock,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use qdrant_client::{
+  Qdrant,
+  qdrant::{
+    Condition, CreateAliasBuilder, CreateCollection, DeletePointsBuilder, Distance, Filter, PointId, PointStruct, PointsIdsList,
+    QueryPointsBuilder, ScrollPoints, UpsertPoints, Value, VectorParams, VectorParamsMap, VectorsConfig, VectorsOutput,
+    point_id::PointIdOptions, vector_output::Vector, vectors_config::Config,
+  },
+};
+use tracing::{info, warn};
+
+use crate::{
+  TOOL_COLLECTION_NAME,
+  collections::{DESCRIPTION_VECTOR, NAME_VECTOR, SCHEMA_VECTOR, collection_name_for},
+  vector_store::{Payload, PayloadFilter, ToolPoint, ToolVectors, VectorStore},
+};
+
+const EMBEDDING_MODEL_METADATA_KEY: &str = "embedding_model";
+const VECTOR_SIZE_METADATA_KEY: &str = "vector_size";
+
+/// Whether a collection stores a single vector per tool, as legacy ones do, or named vectors.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum VectorLayout {
+  Single,
+  Named,
+}
+
+pub(crate) struct QdrantVectorStore {
+  client: Qdrant,
+  /// Layouts by collection or alias name, so searches do not look them up every time. The
+  /// alias entry is refreshed whenever the alias is checked or moved.
+  layouts: RwLock<HashMap<String, VectorLayout>>,
+}
+
+impl QdrantVectorStore {
+  pub(crate) fn new(client: Qdrant) -> Self {
+    Self {
+      client,
+      layouts: RwLock::new(HashMap::new()),
+    }
+  }
+
+  async fn layout(&self, collection: &str) -> Result<VectorLayout> {
+    let cached = self.layouts.read().unwrap_or_else(|e| e.into_inner()).get(collection).copied();
+    if let Some(layout) = cached {
+      return Ok(layout);
+    }
+
+    let layout = match self.vectors_config(collection).await? {
+      Some(Config::Params(_)) => VectorLayout::Single,
+      _ => VectorLayout::Named,
+    };
+    self.cache_layout(collection, layout);
+
+    Ok(layout)
+  }
+
+  fn cache_layout(&self, collection: &str, layout: VectorLayout) {
+    self
+      .layouts
+      .write()
+      .unwrap_or_else(|e| e.into_inner())
+      .insert(collection.to_string(), layout);
+  }
+
+  async fn vectors_config(&self, collection: &str) -> Result<Option<Config>> {
+    let info = self.client.collection_info(collection).await?;
+    let config = info
+      .result
+      .and_then(|info| info.config)
+      .and_then(|config| config.params)
+      .and_then(|params| params.vectors_config)
+      .and_then(|vectors| vectors.config);
+
+    Ok(config)
+  }
+}
+
+#[async_trait]
+impl VectorStore for QdrantVectorStore {
+  /// Deployments created before per-model collections have a real collection under
+  /// `TOOL_COLLECTION_NAME` instead of an alias.
+  async fn active_collection(&self) -> Result<Option<String>> {
+    let aliases = self.client.list_aliases().await?;
+    if let Some(alias) = aliases.aliases.into_iter().find(|a| a.alias_name == TOOL_COLLECTION_NAME) {
+      return Ok(Some(alias.collection_name));
+    }
+
+    if self.client.collection_exists(TOOL_COLLECTION_NAME).await? {
+      return Ok(Some(TOOL_COLLECTION_NAME.to_string()));
+    }
+
+    Ok(None)
+  }
+
+  async fn active_embedding_model(&self) -> Result<Option<String>> {
+    let Some(collection) = self.active_collection().await? else {
+      return Ok(None);
+    };
+
+    let info = self.client.collection_info(collection).await?;
+    let model = info
+      .result
+      .and_then(|info| info.config)
+      .and_then(|config| config.metadata.get(EMBEDDING_MODEL_METADATA_KEY).cloned())
+      .and_then(string_value);
+
+    Ok(model)
+  }
+
+  async fn create_collection(&self, model: &str, vector_size: u64) -> Result<String> {
+    let collection_name = collection_name_for(model, vector_size);
+
+    if self.client.collection_exists(&collection_name).await? {
+      return Ok(collection_name);
+    }
+
+    info!(collection = %collection_name, vector_size, "Creating Qdrant collection");
+
+    let mut metadata = HashMap::new();
+    metadata.insert(EMBEDDING_MODEL_METADATA_KEY.to_string(), model.to_string().into());
+    metadata.insert(VECTOR_SIZE_METADATA_KEY.to_string(), (vector_size as i64).into());
+
+    let vectors = [NAME_VECTOR, DESCRIPTION_VECTOR, SCHEMA_VECTOR]
+      .into_iter()
+      .map(|name| {
+        (
+          name.to_string(),
+          VectorParams {
+            size: vector_size,
+            distance: Distance::Cosine as i32,
+            ..Default::default()
+          },
+        )
+      })
+      .collect();
+
+    self
+      .client
+      .create_collection(CreateCollection {
+        collection_name: collection_name.clone(),
+        vectors_config: Some(VectorsConfig {
+          config: Some(Config::ParamsMap(VectorParamsMap { map: vectors })),
+        }),
+        metadata,
+        ..Default::default()
+      })
+      .await?;
+
+    info!(collection = %collection_name, "Created collection");
+
+    Ok(collection_name)
+  }
+
+  /// Fails if the active collection was built with a different dimension or an older
+  /// single-vector layout.
+  async fn ensure_collection(&self, model: &str, vector_size: u64) -> Result<()> {
+    if let Some(collection) = self.active_collection().await? {
+      return match self.vectors_config(&collection).await? {
+        Some(Config::Params(_)) => anyhow::bail!(
+          "Collection {} stores a single vector per tool; run an embedding migration",
+          collection
+        ),
+        Some(Config::ParamsMap(params)) => match params.map.values().find(|p| p.size != vector_size) {
+          Some(p) => anyhow::bail!(
+            "Collection {} has vector size {} but model {} produces {}; run an embedding migration",
+            collection,
+            p.size,
+            model,
+            vector_size
+          ),
+          None => {
+            self.cache_layout(TOOL_COLLECTION_NAME, VectorLayout::Named);
+            Ok(())
+          }
+        },
+        None => Ok(()),
+      };
+    }
+
+    let collection = self.create_collection(model, vector_size).await?;
+    self.activate_collection(&collection).await
+  }
+
+  /// Creating an alias that already exists reassigns it in a single operation, so readers
+  /// never observe a missing alias. A legacy collection occupying the alias name has to be
+  /// dropped first, which is refused until an embedding migration copied all its tools over.
+  async fn activate_collection(&self, collection: &str) -> Result<()> {
+    let aliases = self.client.list_aliases().await?;
+    let is_alias = aliases.aliases.iter().any(|a| a.alias_name == TOOL_COLLECTION_NAME);
+
+    if !is_alias && self.client.collection_exists(TOOL_COLLECTION_NAME).await? {
+      let migrated: HashSet<String> = self.scroll(collection, None, false).await?.into_iter().map(|p| p.id).collect();
+      let unmigrated = self
+        .scroll(TOOL_COLLECTION_NAME, None, false)
+        .await?
+        .into_iter()
+        .filter(|point| !migrated.contains(&point.id))
+        .count();
+      if unmigrated > 0 {
+        anyhow::bail!(
+          "Legacy collection {} has {} tools missing from {}; run an embedding migration",
+          TOOL_COLLECTION_NAME,
+          unmigrated,
+          collection
+        );
+      }
+
+      info!(
+        collection = TOOL_COLLECTION_NAME,
+        "Dropping migrated legacy collection to replace it with an alias"
+      );
+      self.client.delete_collection(TOOL_COLLECTION_NAME).await?;
+    }
+
+    self
+      .client
+      .create_alias(CreateAliasBuilder::new(collection, TOOL_COLLECTION_NAME))
+      .await?;
+    info!(alias = TOOL_COLLECTION_NAME, collection, "Alias now points at collection");
+
+    self.layouts.write().unwrap_or_else(|e| e.into_inner()).remove(TOOL_COLLECTION_NAME);
+    let layout = self.layout(collection).await?;
+    self.cache_layout(TOOL_COLLECTION_NAME, layout);
+
+    Ok(())
+  }
+
+  async fn upsert(&self, collection: &str, points: Vec<ToolPoint>) -> Result<()> {
+    let points = points
+      .into_iter()
+      .map(|point| {
+        let payload: HashMap<String, Value> = point.payload.into_iter().map(|(k, v)| (k, v.into())).collect();
+        PointStruct::new(point.id, point.vectors, payload)
+      })
+      .collect();
+
+    self
+      .client
+      .upsert_points(UpsertPoints {
+        collection_name: collection.to_string(),
+        wait: Some(true),
+        points,
+        ..Default::default()
+      })
+      .await?;
+
+    Ok(())
+  }
+
+  async fn delete(&self, collection: &str, ids: Vec<String>) -> Result<()> {
+    if ids.is_empty() {
+      return Ok(());
+    }
+
+    let ids: Vec<PointId> = ids.into_iter().map(PointId::from).collect();
+
+    self
+      .client
+      .delete_points(DeletePointsBuilder::new(collection).points(PointsIdsList { ids }).wait(true))
+      .await?;
+
+    Ok(())
+  }
+
+  async fn scroll(&self, collection: &str, filter: Option<&PayloadFilter>, with_vectors: bool) -> Result<Vec<ToolPoint>> {
+    let mut points = Vec::new();
+    let mut next_page = None;
+
+    loop {
+      let response = self
+        .client
+        .scroll(ScrollPoints {
+          collection_name: collection.to_string(),
+          filter: filter.map(to_qdrant_filter),
+          with_payload: Some(true.into()),
+          with_vectors: Some(with_vectors.into()),
+          limit: Some(100),
+          offset: next_page,
+          ..Default::default()
+        })
+        .await?;
+
+      points.extend(
+        response
+          .result
+          .into_iter()
+          .map(|point| to_tool_point(point.id, point.vectors, point.payload)),
+      );
+
+      next_page = response.next_page_offset;
+      if next_page.is_none() {
+        break;
+      }
+    }
+
+    Ok(points)
+  }
+
+  /// Legacy single-vector collections only answer for `DESCRIPTION_VECTOR`.
+  async fn search(
+    &self,
+    collection: &str,
+    vector_name: &str,
+    query: &[f32],
+    filter: Option<&PayloadFilter>,
+    limit: usize,
+  ) -> Result<Vec<(ToolPoint, f32)>> {
+    let mut request = QueryPointsBuilder::new(collection)
+      .query(query.to_vec())
+      .limit(limit as u64)
+      .with_payload(true)
+      .with_vectors(true);
+
+    match self.layout(collection).await? {
+      VectorLayout::Single if vector_name != DESCRIPTION_VECTOR => return Ok(Vec::new()),
+      VectorLayout::Single => {}
+      VectorLayout::Named => request = request.using(vector_name),
+    }
+
+    if let Some(filter) = filter {
+      request = request.filter(to_qdrant_filter(filter));
+    }
+
+    let response = self.client.query(request).await?;
+
+    Ok(
+      response
+        .result
+        .into_iter()
+        .map(|point| (to_tool_point(point.id, point.vectors, point.payload), point.score))
+        .collect(),
+    )
+  }
+}
+
+fn to_qdrant_filter(filter: &PayloadFilter) -> Filter {
+  Filter::must([Condition::matches(filter.key.clone(), filter.any_of.clone())])
+}
+
+fn to_tool_point(id: Option<PointId>, vectors: Option<VectorsOutput>, payload: HashMap<String, Value>) -> ToolPoint {
+  let id = match id.and_then(|id| id.point_id_options) {
+    Some(PointIdOptions::Uuid(uuid)) => uuid,
+    Some(PointIdOptions::Num(num)) => num.to_string(),
+    None => String::new(),
+  };
+
+  let payload: Payload = payload
+    .into_iter()
+    .filter_map(|(key, value)| string_value(value).map(|value| (key, value)))
+    .collect();
+
+  ToolPoint {
+    id,
+    vectors: get_vectors(&vectors),
+    payload,
+  }
+}
+
+fn string_value(value: Value) -> Option<String> {
+  match value.kind {
+    Some(qdrant_client::qdrant::value::Kind::StringValue(s)) => Some(s),
+    _ => None,
+  }
+}
+
+/// Collects the dense vectors of a point by name. Points stored before tools had named
+/// vectors carry a single unnamed vector, which is treated as the description vector.
+fn get_vectors(vectors: &Option<VectorsOutput>) -> ToolVectors {
+  let Some(vectors) = vectors else {
+    return ToolVectors::new();
+  };
+
+  let dense = |vector: Option<Vector>| match vector {
+    Some(Vector::Dense(d)) if !d.data.is_empty() => Some(d.data),
+    other => {
+      if other.is_some() {
+        warn!(vector = ?other, "Vector is not Dense");
+      }
+      None
+    }
+  };
+
+  if let Some(vector) = dense(vectors.get_vector()) {
+    return ToolVectors::from([(DESCRIPTION_VECTOR.to_string(), vector)]);
+  }
+
+  [NAME_VECTOR, DESCRIPTION_VECTOR, SCHEMA_VECTOR]
+    .into_iter()
+    .filter_map(|name| dense(vectors.get_vector_by_name(name)).map(|v| (name.to_string(), v)))
+    .collect()
+}
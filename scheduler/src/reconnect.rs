@@ -0,0 +1,187 @@
+use std::{
+  collections::HashSet,
+  time::{Duration, Instant},
+};
+
+use tracing::{error, info, instrument, warn};
+
+use crate::{
+  MAX_PING_HISTORY, RECONNECT_BACKOFF_BASE, RECONNECT_BACKOFF_MAX,
+  health::{HealthState, ServerHealth},
+  tool_registration::{connect_client, list_tools, store_tools},
+  types::{AppState, BatchId, DynamicMcpClient, ServerStatus},
+};
+
+/// Reconnection attempts for a server whose session died, e.g. because it restarted.
+#[derive(Default)]
+pub(crate) struct ReconnectState {
+  pub(crate) in_progress: bool,
+  pub(crate) failed_attempts: u32,
+  pub(crate) next_attempt: Option<Instant>,
+}
+
+impl ReconnectState {
+  fn backoff(&self) -> Duration {
+    let factor = 2u32.saturating_pow(self.failed_attempts.saturating_sub(1));
+    RECONNECT_BACKOFF_BASE.saturating_mul(factor).min(RECONNECT_BACKOFF_MAX)
+  }
+}
+
+/// A session needs replacing once its transport closed or the server went down. Returns false
+/// while an attempt is running or backing off.
+pub(crate) fn should_reconnect(status: &ServerStatus) -> bool {
+  let session_dead = status.client.is_transport_closed() || status.health.state() == HealthState::Down;
+  let reconnect = &status.reconnect;
+
+  session_dead && !reconnect.in_progress && reconnect.next_attempt.is_none_or(|at| Instant::now() >= at)
+}
+
+/// Re-runs the MCP handshake with `url` and, on success, swaps in the new client and re-syncs
+/// its tool list. The state lock is only held to read settings and to apply the outcome.
+/// Callers mark the attempt as in progress before spawning it.
+#[instrument(skip(state), fields(mcp_url = %url))]
+pub(crate) async fn reconnect_server(state: AppState, url: String) {
+  info!("Reconnecting");
+
+  let result = connect_client(&url).await;
+
+  let tools = match &result {
+    Ok(client) => sync_tools(&state, &url, client).await,
+    Err(_) => None,
+  };
+
+  let mut app_data = state.write().await;
+  let Some(status) = app_data.servers.get_mut(&url) else {
+    info!("Reconnected, but server is no longer monitored");
+    return;
+  };
+
+  let reconnect = &mut status.reconnect;
+  reconnect.in_progress = false;
+
+  match result {
+    Ok(client) => {
+      info!(failed_attempts = reconnect.failed_attempts, "Reconnected");
+      *reconnect = ReconnectState::default();
+
+      let previous = std::mem::replace(&mut status.client, client);
+      previous.cancellation_token().cancel();
+      status.health.record_success();
+      if let Some(tools) = tools {
+        status.tools = tools;
+      }
+    }
+    Err(e) => {
+      reconnect.failed_attempts += 1;
+      let backoff = reconnect.backoff();
+      reconnect.next_attempt = Some(Instant::now() + backoff);
+      warn!(
+        attempt = reconnect.failed_attempts,
+        retry_in_seconds = backoff.as_secs(),
+        error = %e,
+        "Reconnect failed"
+      );
+    }
+  }
+}
+
+/// Connects to a server of a batch restored from Postgres, retrying with the reconnect backoff
+/// until it succeeds or no batch references the server any more.
+#[instrument(skip(state), fields(mcp_url = %url))]
+pub(crate) async fn restore_server(state: AppState, url: String) {
+  let mut attempts = ReconnectState::default();
+
+  loop {
+    let referenced = state.read().await.batch_map.values().any(|batch| batch.urls.contains(&url));
+    if !referenced {
+      info!("Stopped restoring: no batch references the server any more");
+      return;
+    }
+
+    let client = match connect_client(&url).await {
+      Ok(client) => client,
+      Err(e) => {
+        attempts.failed_attempts += 1;
+        let backoff = attempts.backoff();
+        warn!(
+          attempt = attempts.failed_attempts,
+          retry_in_seconds = backoff.as_secs(),
+          error = %e,
+          "Restoring server failed"
+        );
+        tokio::time::sleep(backoff).await;
+        continue;
+      }
+    };
+
+    let tools = sync_tools(&state, &url, &client).await;
+
+    let mut app_data = state.write().await;
+    let active_batches: HashSet<BatchId> = app_data
+      .batch_map
+      .iter()
+      .filter(|(_, batch)| batch.urls.contains(&url))
+      .map(|(id, _)| id.clone())
+      .collect();
+
+    if active_batches.is_empty() {
+      client.cancellation_token().cancel();
+      return;
+    }
+
+    info!(batch_count = active_batches.len(), "Restored server");
+
+    // The server may have been registered again while it was being restored.
+    match app_data.servers.get_mut(&url) {
+      Some(status) => {
+        status.active_batches.extend(active_batches);
+        client.cancellation_token().cancel();
+      }
+      None => {
+        app_data.servers.insert(
+          url,
+          ServerStatus {
+            client,
+            active_batches,
+            tools: tools.unwrap_or_default(),
+            latency_history: Vec::with_capacity(MAX_PING_HISTORY),
+            health: ServerHealth::default(),
+            reconnect: ReconnectState::default(),
+          },
+        );
+      }
+    }
+
+    return;
+  }
+}
+
+/// Re-lists the tools of `url` and updates the stored embeddings. Listing happens without the
+/// state lock; storing holds the write lock, like registrations, so an embedding migration
+/// cannot switch models in between. Returns the listed tool names, or `None` if listing failed.
+async fn sync_tools(state: &AppState, url: &str, client: &DynamicMcpClient) -> Option<HashSet<String>> {
+  let tools = match list_tools(client).await {
+    Ok(tools) => tools,
+    Err(e) => {
+      error!(mcp_url = url, error = %e, "Failed to re-sync tools");
+      return None;
+    }
+  };
+
+  let app_data = state.write().await;
+  let stored = store_tools(
+    &tools,
+    url,
+    app_data.vector_store.as_ref(),
+    &app_data.embedding_model,
+    &app_data.embedding_template,
+  )
+  .await;
+  drop(app_data);
+
+  if let Err(e) = stored {
+    error!(mcp_url = url, error = %e, "Failed to store re-synced tools");
+  }
+
+  Some(tools.iter().map(|tool| tool.name.to_string()).collect())
+}
@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+
+use axum::{
+  Json,
+  extract::{Query, State},
+  http::StatusCode,
+  response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, types::Json as JsonColumn};
+use tracing::{error, instrument};
+use uuid::Uuid;
+
+use crate::{DEFAULT_ROUTING_DECISION_LIMIT, MAX_ROUTING_DECISION_LIMIT, types::AppState};
+
+/// How the selected tool of a cluster was chosen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RoutingStrategy {
+  /// Lowest routing cost among the tools whose error circuit is closed.
+  LowestCost,
+  /// Every tool of the cluster had its error circuit open, so all of them were ranked.
+  CircuitOpenFallback,
+  /// Cheapest affordable tool expected to meet the latency target.
+  Cheapest,
+  /// No affordable tool was expected to meet the latency target, so the fastest one was
+  /// picked.
+  LatencyTargetMissed,
+  /// Lowest routing cost among the tools the remaining budget can afford.
+  WithinBudget,
+  /// Most likely to finish within the deadline among the tools whose p95 latency meets it.
+  MostLikelyOnTime,
+  /// No tool's p95 latency met the deadline, so the one most likely to finish within it
+  /// anyway was picked.
+  DeadlineUnreachable,
+  /// The remaining budget could afford no tool of the cluster, so none was selected and the
+  /// cluster was left out of the response.
+  OverBudget,
+}
+
+impl RoutingStrategy {
+  fn as_str(self) -> &'static str {
+    match self {
+      RoutingStrategy::LowestCost => "lowest_cost",
+      RoutingStrategy::CircuitOpenFallback => "circuit_open_fallback",
+      RoutingStrategy::Cheapest => "cheapest",
+      RoutingStrategy::LatencyTargetMissed => "latency_target_missed",
+      RoutingStrategy::WithinBudget => "within_budget",
+      RoutingStrategy::MostLikelyOnTime => "most_likely_on_time",
+      RoutingStrategy::DeadlineUnreachable => "deadline_unreachable",
+      RoutingStrategy::OverBudget => "over_budget",
+    }
+  }
+}
+
+/// How `/search` estimates the latency of a cluster's members.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RoutingMode {
+  /// Recent mean latency of the tool, whatever it was called with.
+  #[default]
+  Latency,
+  /// Mean latency of the tool's calls that shared the intended call's argument features.
+  InputAware,
+  /// Recent mean latency, but picks the cheapest tool expected to meet the latency target.
+  /// Tools the batch's remaining budget cannot afford are left out.
+  Cheapest,
+  /// Recent mean latency, leaving out the tools the batch's remaining budget cannot afford.
+  FastestWithinBudget,
+}
+
+impl RoutingMode {
+  fn as_str(self) -> &'static str {
+    match self {
+      RoutingMode::Latency => "latency",
+      RoutingMode::InputAware => "input_aware",
+      RoutingMode::Cheapest => "cheapest",
+      RoutingMode::FastestWithinBudget => "fastest_within_budget",
+    }
+  }
+
+  pub(crate) fn is_budget_aware(self) -> bool {
+    matches!(self, RoutingMode::Cheapest | RoutingMode::FastestWithinBudget)
+  }
+}
+
+/// A member of the cluster a tool was selected from. `cost_ms` and `price`, the expected
+/// price of a call, are only set for the tools that were ranked; `price` only for priced
+/// tools in budget-aware modes, and `on_time_probability` and `p95_latency_ms` only when
+/// the search had a deadline.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct RoutingCandidate {
+  pub(crate) name: String,
+  pub(crate) mcp_url: String,
+  pub(crate) score: Option<f32>,
+  pub(crate) cost_ms: Option<f64>,
+  pub(crate) price: Option<f64>,
+  pub(crate) on_time_probability: Option<f64>,
+  pub(crate) p95_latency_ms: Option<f64>,
+  pub(crate) circuit_open: bool,
+}
+
+/// The `/search` request the decisions were made for.
+pub(crate) struct RoutingRequest {
+  pub(crate) batch_id: String,
+  pub(crate) query: Option<String>,
+  pub(crate) mode: RoutingMode,
+  pub(crate) rtt_weight: f64,
+  pub(crate) output_token_weight: f64,
+  pub(crate) features: Option<HashMap<String, String>>,
+  pub(crate) latency_target_ms: Option<f64>,
+  pub(crate) remaining_budget: Option<f64>,
+  pub(crate) deadline_ms: Option<f64>,
+}
+
+/// The selected tool is missing for `RoutingStrategy::OverBudget` decisions.
+pub(crate) struct RoutingDecision {
+  pub(crate) strategy: RoutingStrategy,
+  pub(crate) selected_tool_name: Option<String>,
+  pub(crate) selected_mcp_url: Option<String>,
+  pub(crate) candidates: Vec<RoutingCandidate>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct RoutingDecisionsQuery {
+  pub(crate) batch_id: Option<String>,
+  pub(crate) tool_name: Option<String>,
+  pub(crate) mcp_url: Option<String>,
+  pub(crate) from: Option<DateTime<Utc>>,
+  pub(crate) to: Option<DateTime<Utc>>,
+  pub(crate) limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct RoutingDecisionRecord {
+  pub(crate) id: Uuid,
+  pub(crate) batch_id: String,
+  pub(crate) query: Option<String>,
+  pub(crate) mode: String,
+  pub(crate) strategy: String,
+  pub(crate) rtt_weight: f64,
+  pub(crate) output_token_weight: f64,
+  pub(crate) features: Option<JsonColumn<HashMap<String, String>>>,
+  pub(crate) latency_target_ms: Option<f64>,
+  pub(crate) remaining_budget: Option<f64>,
+  pub(crate) deadline_ms: Option<f64>,
+  pub(crate) selected_tool_name: Option<String>,
+  pub(crate) selected_mcp_url: Option<String>,
+  pub(crate) candidates: JsonColumn<Vec<RoutingCandidate>>,
+  pub(crate) timestamp: DateTime<Utc>,
+}
+
+/// Stores the decisions behind one `/search` response. Failures are logged; the search
+/// itself has already been answered.
+pub(crate) async fn record_decisions(pool: PgPool, request: RoutingRequest, decisions: Vec<RoutingDecision>) {
+  if decisions.is_empty() {
+    return;
+  }
+
+  let mut strategies = Vec::with_capacity(decisions.len());
+  let mut tool_names = Vec::with_capacity(decisions.len());
+  let mut mcp_urls = Vec::with_capacity(decisions.len());
+  let mut candidates = Vec::with_capacity(decisions.len());
+  for decision in decisions {
+    strategies.push(decision.strategy.as_str().to_string());
+    tool_names.push(decision.selected_tool_name);
+    mcp_urls.push(decision.selected_mcp_url);
+    candidates.push(serde_json::to_value(decision.candidates).unwrap_or_default());
+  }
+
+  let result = sqlx::query!(
+    r#"
+    INSERT INTO routing_decisions (
+      batch_id, query, mode, rtt_weight, output_token_weight, features, latency_target_ms, remaining_budget, deadline_ms,
+      strategy, selected_tool_name, selected_mcp_url, candidates
+    )
+    SELECT $1, $2, $3, $4, $5, $6, $7, $8, $9, *
+    FROM UNNEST($10::TEXT[], $11::TEXT[], $12::TEXT[], $13::JSONB[])
+    "#,
+    request.batch_id,
+    request.query,
+    request.mode.as_str(),
+    request.rtt_weight,
+    request.output_token_weight,
+    request.features.map(JsonColumn) as Option<JsonColumn<HashMap<String, String>>>,
+    request.latency_target_ms,
+    request.remaining_budget,
+    request.deadline_ms,
+    &strategies,
+    &tool_names as &[Option<String>],
+    &mcp_urls as &[Option<String>],
+    &candidates
+  )
+  .execute(&pool)
+  .await;
+
+  if let Err(e) = result {
+    error!(batch_id = request.batch_id, error = %e, "Failed to record routing decisions");
+  }
+}
+
+/// Lists recorded routing decisions, newest first. Every filter is optional; `tool_name`
+/// and `mcp_url` match the selected tool.
+#[instrument(skip_all, fields(batch_id = ?params.batch_id, tool_name = ?params.tool_name, mcp_url = ?params.mcp_url))]
+pub(crate) async fn list_routing_decisions(
+  State(state): State<AppState>,
+  Query(params): Query<RoutingDecisionsQuery>,
+) -> impl IntoResponse {
+  let pool = state.read().await.pool.clone();
+  let limit = params
+    .limit
+    .unwrap_or(DEFAULT_ROUTING_DECISION_LIMIT)
+    .clamp(1, MAX_ROUTING_DECISION_LIMIT);
+
+  let result = sqlx::query_as!(
+    RoutingDecisionRecord,
+    r#"
+    SELECT
+      id, batch_id, query, mode, strategy, rtt_weight, output_token_weight,
+      features AS "features: JsonColumn<HashMap<String, String>>",
+      latency_target_ms, remaining_budget, deadline_ms,
+      selected_tool_name, selected_mcp_url,
+      candidates AS "candidates: JsonColumn<Vec<RoutingCandidate>>",
+      timestamp
+    FROM routing_decisions
+    WHERE ($1::TEXT IS NULL OR batch_id = $1)
+      AND ($2::TEXT IS NULL OR selected_tool_name = $2)
+      AND ($3::TEXT IS NULL OR selected_mcp_url = $3)
+      AND ($4::TIMESTAMPTZ IS NULL OR timestamp >= $4)
+      AND ($5::TIMESTAMPTZ IS NULL OR timestamp <= $5)
+    ORDER BY timestamp DESC
+    LIMIT $6
+    "#,
+    params.batch_id,
+    params.tool_name,
+    params.mcp_url,
+    params.from,
+    params.to,
+    limit
+  )
+  .fetch_all(&pool)
+  .await;
+
+  match result {
+    Ok(decisions) => (StatusCode::OK, Json(decisions)).into_response(),
+    Err(e) => {
+      error!(error = %e, "Failed to list routing decisions");
+      (StatusCode::INTERNAL_SERVER_ERROR, Json(Vec::<RoutingDecisionRecord>::new())).into_response()
+    }
+  }
+}
@@ -0,0 +1,65 @@
+use anyhow::Result;
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::{Resource, trace::SdkTracerProvider};
+use tracing_subscriber::{EnvFilter, Layer, layer::SubscriberExt, util::SubscriberInitExt};
+
+const SERVICE_NAME: &str = "mcp-scheduler";
+
+/// Keeps the OpenTelemetry pipeline alive; `shutdown` flushes spans that are still buffered.
+pub(crate) struct Telemetry {
+  tracer_provider: Option<SdkTracerProvider>,
+}
+
+impl Telemetry {
+  pub(crate) fn shutdown(self) {
+    if let Some(provider) = self.tracer_provider
+      && let Err(e) = provider.shutdown()
+    {
+      tracing::error!(error = %e, "Failed to flush OpenTelemetry spans");
+    }
+  }
+}
+
+/// Installs the global `tracing` subscriber.
+///
+/// `RUST_LOG` selects what is logged (default `info`) and `LOG_FORMAT=json` switches to one
+/// JSON object per line. When `OTEL_EXPORTER_OTLP_ENDPOINT` is set, spans are also exported
+/// over OTLP/HTTP; the exporter reads the other standard `OTEL_*` variables itself.
+pub(crate) fn init_telemetry() -> Result<Telemetry> {
+  let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+
+  let fmt_layer = match std::env::var("LOG_FORMAT").as_deref() {
+    Ok("json") => tracing_subscriber::fmt::layer().json().with_current_span(true).boxed(),
+    _ => tracing_subscriber::fmt::layer().boxed(),
+  };
+
+  let tracer_provider = match std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+    Ok(_) => {
+      let exporter = SpanExporter::builder().with_http().build()?;
+      Some(
+        SdkTracerProvider::builder()
+          .with_batch_exporter(exporter)
+          .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+          .build(),
+      )
+    }
+    Err(_) => None,
+  };
+
+  let otel_layer = tracer_provider
+    .as_ref()
+    .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME)));
+
+  tracing_subscriber::registry()
+    .with(filter)
+    .with(fmt_layer)
+    .with(otel_layer)
+    .try_init()?;
+
+  if tracer_provider.is_some() {
+    tracing::info!("Exporting spans over OTLP");
+  }
+
+  Ok(Telemetry { tracer_provider })
+}
@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use sqlx::PgPool;
+use tokio_cron_scheduler::{Job, JobScheduler};
+use tracing::{error, info, instrument};
+
+use crate::{HOURLY_ROLLUP_RETENTION_DAYS, TOOL_CALL_MAINTENANCE_SCHEDULE, ping_history};
+
+/// Bucket sizes, in seconds, of the rollups kept in `tool_call_rollups`.
+const ROLLUP_RESOLUTIONS: [i32; 2] = [60 * 60, 24 * 60 * 60];
+
+/// Calls to one tool over some period, from raw rows, rollups or both. Percentiles of
+/// several buckets are averaged weighted by their successful calls, so they are approximate
+/// once rollups are involved.
+pub(crate) struct ToolCallAggregate {
+  pub(crate) tool_name: String,
+  pub(crate) mcp_url: String,
+  pub(crate) call_count: i64,
+  pub(crate) error_count: i64,
+  pub(crate) success_count: i64,
+  pub(crate) latency_sum_ms: f64,
+  pub(crate) p50_latency_ms: Option<f64>,
+  pub(crate) p95_latency_ms: Option<f64>,
+  pub(crate) p99_latency_ms: Option<f64>,
+  pub(crate) last_called_at: DateTime<Utc>,
+  /// Output sizes of the successful calls that reported them.
+  pub(crate) response_bytes_sum: f64,
+  pub(crate) response_bytes_count: i64,
+  pub(crate) response_tokens_sum: f64,
+  pub(crate) response_tokens_count: i64,
+  /// What the calls cost, see `tool_prices`.
+  pub(crate) price_sum: f64,
+}
+
+impl ToolCallAggregate {
+  pub(crate) fn merge(&mut self, other: ToolCallAggregate) {
+    let weighted = |a: Option<f64>, b: Option<f64>| match (a, b) {
+      (Some(a), Some(b)) => {
+        Some((a * self.success_count as f64 + b * other.success_count as f64) / (self.success_count + other.success_count) as f64)
+      }
+      (a, b) => a.or(b),
+    };
+
+    self.p50_latency_ms = weighted(self.p50_latency_ms, other.p50_latency_ms);
+    self.p95_latency_ms = weighted(self.p95_latency_ms, other.p95_latency_ms);
+    self.p99_latency_ms = weighted(self.p99_latency_ms, other.p99_latency_ms);
+    self.call_count += other.call_count;
+    self.error_count += other.error_count;
+    self.success_count += other.success_count;
+    self.latency_sum_ms += other.latency_sum_ms;
+    self.last_called_at = self.last_called_at.max(other.last_called_at);
+    self.response_bytes_sum += other.response_bytes_sum;
+    self.response_bytes_count += other.response_bytes_count;
+    self.response_tokens_sum += other.response_tokens_sum;
+    self.response_tokens_count += other.response_tokens_count;
+    self.price_sum += other.price_sum;
+  }
+
+  pub(crate) fn mean_latency_ms(&self) -> Option<f64> {
+    (self.success_count > 0).then(|| self.latency_sum_ms / self.success_count as f64)
+  }
+
+  pub(crate) fn mean_response_bytes(&self) -> Option<f64> {
+    (self.response_bytes_count > 0).then(|| self.response_bytes_sum / self.response_bytes_count as f64)
+  }
+
+  pub(crate) fn mean_response_tokens(&self) -> Option<f64> {
+    (self.response_tokens_count > 0).then(|| self.response_tokens_sum / self.response_tokens_count as f64)
+  }
+}
+
+/// Raw rows older than this have been, or are about to be, deleted, so reads answer
+/// anything before it from the rollups.
+pub(crate) fn raw_retained_from(retention: TimeDelta) -> DateTime<Utc> {
+  let from = Utc::now() - retention;
+  from.duration_trunc(TimeDelta::hours(1)).unwrap_or(from)
+}
+
+/// Hourly rollups are kept for `HOURLY_ROLLUP_RETENTION_DAYS`, or as long as raw rows if that
+/// is longer, so they always cover everything raw rows no longer do.
+fn hourly_rollup_retention(retention: TimeDelta) -> TimeDelta {
+  TimeDelta::days(HOURLY_ROLLUP_RETENTION_DAYS).max(retention)
+}
+
+/// The first whole day still covered by hourly rollups. Reads use daily rollups before it and
+/// hourly ones from it on, so no call is counted in both.
+fn hourly_rollups_from(retention: TimeDelta) -> DateTime<Utc> {
+  let from = Utc::now() - hourly_rollup_retention(retention);
+  from.duration_trunc(TimeDelta::days(1)).map_or(from, |day| day + TimeDelta::days(1))
+}
+
+/// Aggregates the rollups between `from` and `to` per tool, from the hourly rollups while
+/// they are kept and from the daily ones before that. Buckets overlapping `from` are
+/// included whole; `to` must be on an hour boundary, like `raw_retained_from`.
+pub(crate) async fn rollup_aggregates(
+  pool: &PgPool,
+  from: DateTime<Utc>,
+  to: DateTime<Utc>,
+  retention: TimeDelta,
+  mcp_urls: Option<&[String]>,
+  tool_name: Option<&str>,
+) -> Result<Vec<ToolCallAggregate>> {
+  let [hourly, daily] = ROLLUP_RESOLUTIONS;
+
+  let aggregates = sqlx::query_as!(
+    ToolCallAggregate,
+    r#"
+    SELECT
+      tool_name,
+      mcp_url,
+      SUM(call_count)::BIGINT AS "call_count!",
+      SUM(error_count)::BIGINT AS "error_count!",
+      SUM(success_count)::BIGINT AS "success_count!",
+      SUM(latency_sum_ms) AS "latency_sum_ms!",
+      SUM(p50_latency_ms * success_count) / NULLIF(SUM(success_count) FILTER (WHERE p50_latency_ms IS NOT NULL), 0)
+        AS p50_latency_ms,
+      SUM(p95_latency_ms * success_count) / NULLIF(SUM(success_count) FILTER (WHERE p95_latency_ms IS NOT NULL), 0)
+        AS p95_latency_ms,
+      SUM(p99_latency_ms * success_count) / NULLIF(SUM(success_count) FILTER (WHERE p99_latency_ms IS NOT NULL), 0)
+        AS p99_latency_ms,
+      MAX(last_called_at) AS "last_called_at!",
+      SUM(response_bytes_sum) AS "response_bytes_sum!",
+      SUM(response_bytes_count)::BIGINT AS "response_bytes_count!",
+      SUM(response_tokens_sum) AS "response_tokens_sum!",
+      SUM(response_tokens_count)::BIGINT AS "response_tokens_count!",
+      SUM(price_sum) AS "price_sum!"
+    FROM tool_call_rollups
+    WHERE (
+        (bucket_seconds = $1 AND bucket_start >= $5 AND bucket_start > $3::TIMESTAMPTZ - $1 * INTERVAL '1 second')
+        OR (bucket_seconds = $2 AND bucket_start < $5 AND bucket_start > $3::TIMESTAMPTZ - $2 * INTERVAL '1 second')
+      )
+      AND bucket_start < $4
+      AND ($6::TEXT[] IS NULL OR mcp_url = ANY($6))
+      AND ($7::TEXT IS NULL OR tool_name = $7)
+    GROUP BY tool_name, mcp_url
+    "#,
+    hourly,
+    daily,
+    from,
+    to,
+    hourly_rollups_from(retention),
+    mcp_urls,
+    tool_name
+  )
+  .fetch_all(pool)
+  .await?;
+
+  Ok(aggregates)
+}
+
+/// Merges aggregates of the same tool, e.g. from raw rows and rollups.
+pub(crate) fn merge_aggregates(aggregates: impl IntoIterator<Item = ToolCallAggregate>) -> Vec<ToolCallAggregate> {
+  let mut merged: HashMap<(String, String), ToolCallAggregate> = HashMap::new();
+
+  for aggregate in aggregates {
+    match merged.get_mut(&(aggregate.tool_name.clone(), aggregate.mcp_url.clone())) {
+      Some(existing) => existing.merge(aggregate),
+      None => {
+        merged.insert((aggregate.tool_name.clone(), aggregate.mcp_url.clone()), aggregate);
+      }
+    }
+  }
+
+  let mut merged: Vec<_> = merged.into_values().collect();
+  merged.sort_by(|a, b| (&a.mcp_url, &a.tool_name).cmp(&(&b.mcp_url, &b.tool_name)));
+  merged
+}
+
+/// Schedules `run_maintenance` and ping retention on `TOOL_CALL_MAINTENANCE_SCHEDULE` and runs
+/// them once right away, so rows logged while the scheduler was stopped are rolled up promptly.
+pub(crate) async fn start_maintenance(pool: PgPool, retention: TimeDelta) -> Result<JobScheduler> {
+  let scheduler = JobScheduler::new().await?;
+
+  let job_pool = pool.clone();
+  scheduler
+    .add(Job::new_async(TOOL_CALL_MAINTENANCE_SCHEDULE, move |_, _| {
+      let pool = job_pool.clone();
+      Box::pin(async move {
+        run_maintenance(&pool, retention).await;
+        ping_history::apply_retention(&pool).await;
+      })
+    })?)
+    .await?;
+
+  scheduler.start().await?;
+
+  tokio::spawn(async move {
+    run_maintenance(&pool, retention).await;
+    ping_history::apply_retention(&pool).await;
+  });
+
+  Ok(scheduler)
+}
+
+/// Rolls every completed bucket into `tool_call_rollups`, then deletes raw rows older than
+/// `retention` and hourly rollups older than `hourly_rollup_retention`. Raw rows are only
+/// deleted once every resolution has rolled them up.
+#[instrument(skip_all, fields(retention_days = retention.num_days()))]
+pub(crate) async fn run_maintenance(pool: &PgPool, retention: TimeDelta) {
+  for bucket_seconds in ROLLUP_RESOLUTIONS {
+    if let Err(e) = roll_up(pool, bucket_seconds).await {
+      error!(bucket_seconds, error = %e, "Failed to roll up tool calls");
+      return;
+    }
+  }
+
+  let result = sqlx::query!(
+    r#"
+    DELETE FROM tool_call_results
+    WHERE timestamp < LEAST($1, (SELECT MIN(rolled_until) FROM tool_call_rollup_watermarks))
+    "#,
+    raw_retained_from(retention)
+  )
+  .execute(pool)
+  .await;
+
+  match result {
+    Ok(result) => info!(deleted = result.rows_affected(), "Applied tool call retention"),
+    Err(e) => error!(error = %e, "Failed to delete expired tool calls"),
+  }
+
+  let result = sqlx::query!(
+    "DELETE FROM tool_call_rollups WHERE bucket_seconds = $1 AND bucket_start < $2",
+    ROLLUP_RESOLUTIONS[0],
+    Utc::now() - hourly_rollup_retention(retention)
+  )
+  .execute(pool)
+  .await;
+
+  if let Err(e) = result {
+    error!(error = %e, "Failed to delete expired hourly rollups");
+  }
+}
+
+/// Rolls the raw rows between the watermark of `bucket_seconds` and the start of the current
+/// bucket, then moves the watermark. Calls flagged as unknown tool or batch are not rolled up.
+async fn roll_up(pool: &PgPool, bucket_seconds: i32) -> Result<()> {
+  let mut tx = pool.begin().await?;
+
+  let to = Utc::now()
+    .duration_trunc(TimeDelta::seconds(bucket_seconds as i64))
+    .unwrap_or_else(|_| Utc::now());
+
+  let from = sqlx::query_scalar!(
+    r#"
+    SELECT COALESCE(
+      (SELECT rolled_until FROM tool_call_rollup_watermarks WHERE bucket_seconds = $1 FOR UPDATE),
+      (SELECT MIN(timestamp) FROM tool_call_results),
+      $2
+    ) AS "from!"
+    "#,
+    bucket_seconds,
+    to
+  )
+  .fetch_one(&mut *tx)
+  .await?;
+
+  if from >= to {
+    return Ok(());
+  }
+
+  let rolled = sqlx::query!(
+    r#"
+    INSERT INTO tool_call_rollups (
+      tool_name, mcp_url, bucket_seconds, bucket_start, call_count, error_count, success_count, latency_sum_ms,
+      p50_latency_ms, p95_latency_ms, p99_latency_ms, last_called_at, response_bytes_sum, response_bytes_count,
+      response_tokens_sum, response_tokens_count, price_sum
+    )
+    SELECT
+      tool_name,
+      mcp_url,
+      $1::INTEGER,
+      TO_TIMESTAMP(FLOOR(EXTRACT(EPOCH FROM timestamp) / $1::INTEGER) * $1::INTEGER) AS bucket_start,
+      COUNT(*),
+      COUNT(*) FILTER (WHERE is_error),
+      COUNT(*) FILTER (WHERE NOT is_error),
+      COALESCE(SUM(total_time_ms) FILTER (WHERE NOT is_error), 0)::FLOAT8,
+      PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY total_time_ms) FILTER (WHERE NOT is_error),
+      PERCENTILE_CONT(0.95) WITHIN GROUP (ORDER BY total_time_ms) FILTER (WHERE NOT is_error),
+      PERCENTILE_CONT(0.99) WITHIN GROUP (ORDER BY total_time_ms) FILTER (WHERE NOT is_error),
+      MAX(timestamp),
+      COALESCE(SUM(response_bytes) FILTER (WHERE NOT is_error), 0)::FLOAT8,
+      COUNT(response_bytes) FILTER (WHERE NOT is_error),
+      COALESCE(SUM(response_tokens) FILTER (WHERE NOT is_error), 0)::FLOAT8,
+      COUNT(response_tokens) FILTER (WHERE NOT is_error),
+      COALESCE(SUM(price), 0)
+    FROM tool_call_results
+    WHERE timestamp >= $2 AND timestamp < $3 AND NOT unknown_tool AND NOT unknown_batch
+    GROUP BY tool_name, mcp_url, bucket_start
+    ON CONFLICT (tool_name, mcp_url, bucket_seconds, bucket_start) DO UPDATE SET
+      call_count = EXCLUDED.call_count,
+      error_count = EXCLUDED.error_count,
+      success_count = EXCLUDED.success_count,
+      latency_sum_ms = EXCLUDED.latency_sum_ms,
+      p50_latency_ms = EXCLUDED.p50_latency_ms,
+      p95_latency_ms = EXCLUDED.p95_latency_ms,
+      p99_latency_ms = EXCLUDED.p99_latency_ms,
+      last_called_at = EXCLUDED.last_called_at,
+      response_bytes_sum = EXCLUDED.response_bytes_sum,
+      response_bytes_count = EXCLUDED.response_bytes_count,
+      response_tokens_sum = EXCLUDED.response_tokens_sum,
+      response_tokens_count = EXCLUDED.response_tokens_count,
+      price_sum = EXCLUDED.price_sum
+    "#,
+    bucket_seconds,
+    from,
+    to
+  )
+  .execute(&mut *tx)
+  .await?
+  .rows_affected();
+
+  sqlx::query!(
+    r#"
+    INSERT INTO tool_call_rollup_watermarks (bucket_seconds, rolled_until)
+    VALUES ($1, $2)
+    ON CONFLICT (bucket_seconds) DO UPDATE SET rolled_until = EXCLUDED.rolled_until
+    "#,
+    bucket_seconds,
+    to
+  )
+  .execute(&mut *tx)
+  .await?;
+
+  tx.commit().await?;
+
+  info!(bucket_seconds, buckets = rolled, "Rolled up tool calls");
+
+  Ok(())
+}
@@ -1,44 +1,734 @@
-use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
-use serde::Deserialize;
+use anyhow::Result;
+use axum::{
+  Json,
+  body::Body,
+  extract::State,
+  http::{HeaderMap, StatusCode, header},
+  response::IntoResponse,
+};
+use futures::StreamExt;
+use rmcp::model::JsonObject;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::{error, info, instrument, warn};
 
-use crate::types::AppState;
+use std::{
+  collections::{HashMap, HashSet},
+  time::Duration,
+};
 
+use crate::{
+  BYTES_PER_TOKEN, LOG_BATCH_MAX_BODY_BYTES, MAX_NDJSON_LINE_BYTES, MAX_TOOL_CALL_FEATURES,
+  log_writer::LogWriter,
+  prometheus_metrics::record_tool_call,
+  tool_prices::load_prices,
+  types::{AppData, AppState},
+};
+
+/// Content types `log_tool_calls` reads line by line.
+const NDJSON_CONTENT_TYPES: [&str; 2] = ["application/x-ndjson", "application/jsonl"];
+
+/// `tool_call_results.source` of calls reported by agents.
+const AGENT_SOURCE: &str = "agent";
+
+/// Why a tool call failed, stored in `tool_call_results.error_category`.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ErrorCategory {
+  Timeout,
+  Transport,
+  /// The tool ran and reported an error.
+  ToolError,
+  /// The call was rejected before it ran, e.g. for invalid arguments.
+  Validation,
+}
+
+impl ErrorCategory {
+  pub(crate) fn as_str(self) -> &'static str {
+    match self {
+      ErrorCategory::Timeout => "timeout",
+      ErrorCategory::Transport => "transport",
+      ErrorCategory::ToolError => "tool_error",
+      ErrorCategory::Validation => "validation",
+    }
+  }
+}
+
+/// What `/log` does with calls to tools the server did not list, and with calls naming a
+/// batch that is unknown, expired or does not include the server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum UnknownToolPolicy {
+  Reject,
+  /// Stores the call with `tool_call_results.unknown_tool` or `unknown_batch` set. Such calls
+  /// are kept out of routing, the circuit breaker, statistics and rollups.
+  Flag,
+}
+
+/// Checks `/log` and `/log/batch` run against the catalog, read from `LOG_UNKNOWN_TOOLS`
+/// (`reject` or `flag`) and `LOG_REQUIRE_BATCH_ID` at startup.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct LogValidation {
+  pub(crate) unknown_tools: UnknownToolPolicy,
+  pub(crate) require_batch_id: bool,
+}
+
+/// Everything but the tool, server, duration and outcome is optional.
 #[derive(Deserialize)]
 pub(crate) struct LogToolCallRequest {
   pub(crate) tool_name: String,
   pub(crate) mcp_url: String,
   pub(crate) total_time_ms: u64,
   pub(crate) is_error: bool,
+  /// Batch the call was made through; counts as activity on it.
+  pub(crate) batch_id: Option<String>,
+  /// Agent or conversation that made the call.
+  pub(crate) session_id: Option<String>,
+  pub(crate) request_bytes: Option<u64>,
+  pub(crate) response_bytes: Option<u64>,
+  /// Estimated LLM tokens of the output, for agents that count them.
+  pub(crate) response_tokens: Option<u64>,
+  pub(crate) error_category: Option<ErrorCategory>,
+  /// JSON-RPC error code returned by the server.
+  pub(crate) mcp_error_code: Option<i32>,
+  pub(crate) time_to_first_byte_ms: Option<u64>,
+  pub(crate) attributes: Option<JsonObject>,
+  /// Argument features, e.g. the target domain, that input-aware routing predicts latency
+  /// from. At most `MAX_TOOL_CALL_FEATURES`.
+  pub(crate) features: Option<HashMap<String, String>>,
+  /// Set by `check_catalog` when the tool is not in the server's catalog.
+  #[serde(skip)]
+  pub(crate) unknown_tool: bool,
+  /// Set by `check_catalog` when `batch_id` does not name a live batch including the server.
+  /// Such calls are neither charged to the batch nor count as activity on it.
+  #[serde(skip)]
+  pub(crate) unknown_batch: bool,
 }
 
+#[instrument(
+  skip_all,
+  fields(tool_name = %payload.tool_name, mcp_url = %payload.mcp_url, batch_id = ?payload.batch_id)
+)]
 pub(crate) async fn log_tool_call(State(state): State<AppState>, Json(payload): Json<LogToolCallRequest>) -> impl IntoResponse {
-  println!("recieved logging request");
-  let app_data = state.read().await;
-  let pool = &app_data.pool;
+  let mut payload = match validate(payload) {
+    Ok(payload) => payload,
+    Err(error) => return (StatusCode::UNPROCESSABLE_ENTITY, error).into_response(),
+  };
+
+  let log_writer = {
+    let app_data = state.read().await;
+    if let Err(error) = check_catalog(&app_data, &mut payload) {
+      warn!(error, "Rejected tool call");
+      return (StatusCode::UNPROCESSABLE_ENTITY, error).into_response();
+    }
+    touch_record_batch(&app_data, &payload);
+    app_data.log_writer.clone()
+  };
+
+  let (is_error, total_time_ms) = (payload.is_error, payload.total_time_ms);
+
+  match log_writer.enqueue(payload).await {
+    Ok(()) => {
+      info!(is_error, total_time_ms, "Logged tool call");
+      StatusCode::ACCEPTED.into_response()
+    }
+    Err(_) => {
+      warn!("Log queue is full, rejecting tool call");
+      StatusCode::SERVICE_UNAVAILABLE.into_response()
+    }
+  }
+}
+
+/// Logs many tool calls at once. The body is either a JSON array of `LogToolCallRequest`s or,
+/// with `Content-Type: application/x-ndjson`, one per line; NDJSON bodies are streamed.
+/// Invalid records, including ones failing `check_catalog`, are skipped and reported by their
+/// zero-based index, or line for NDJSON; the others are queued for the log writer. A JSON
+/// body that is not an array, or a body that cannot be read to the end, is answered with a
+/// 400. A JSON body over `LOG_BATCH_MAX_BODY_BYTES` or an NDJSON line over
+/// `MAX_NDJSON_LINE_BYTES` is answered with a 413; NDJSON records before it stay queued. Once
+/// the queue stays full, the remaining records are rejected and the response is a 503.
+#[instrument(skip_all)]
+pub(crate) async fn log_tool_calls(State(state): State<AppState>, headers: HeaderMap, body: Body) -> impl IntoResponse {
+  let is_ndjson = headers
+    .get(header::CONTENT_TYPE)
+    .and_then(|value| value.to_str().ok())
+    .is_some_and(|value| NDJSON_CONTENT_TYPES.iter().any(|ndjson| value.starts_with(ndjson)));
+
+  let mut ingest = BatchIngest::new(state).await;
+
+  if is_ndjson {
+    if let Err(status) = ingest_ndjson(body, &mut ingest).await {
+      return (status, Json(ingest.into_response())).into_response();
+    }
+  } else {
+    let bytes = match read_body(body, LOG_BATCH_MAX_BODY_BYTES).await {
+      Ok(bytes) => bytes,
+      Err(status) => return status.into_response(),
+    };
+    let Ok(records) = serde_json::from_slice::<Vec<serde_json::Value>>(&bytes) else {
+      return StatusCode::BAD_REQUEST.into_response();
+    };
+    for (index, record) in records.into_iter().enumerate() {
+      ingest.push(index, serde_json::from_value(record).map_err(|e| e.to_string())).await;
+    }
+  }
+
+  info!(accepted = ingest.accepted, rejected = ingest.rejected.len(), "Logged tool calls");
+
+  let status = if ingest.queue_full {
+    StatusCode::SERVICE_UNAVAILABLE
+  } else {
+    StatusCode::OK
+  };
+
+  (status, Json(ingest.into_response())).into_response()
+}
+
+#[derive(Serialize)]
+pub(crate) struct LogRecordError {
+  pub(crate) index: usize,
+  pub(crate) error: String,
+}
+
+#[derive(Serialize)]
+pub(crate) struct LogBatchResponse {
+  pub(crate) accepted: usize,
+  pub(crate) rejected: Vec<LogRecordError>,
+}
+
+struct BatchIngest {
+  state: AppState,
+  log_writer: LogWriter,
+  accepted: usize,
+  rejected: Vec<LogRecordError>,
+  queue_full: bool,
+}
+
+impl BatchIngest {
+  async fn new(state: AppState) -> Self {
+    let log_writer = state.read().await.log_writer.clone();
+    Self {
+      state,
+      log_writer,
+      accepted: 0,
+      rejected: Vec::new(),
+      queue_full: false,
+    }
+  }
+
+  /// Queues a valid record. After the queue stayed full once, the remaining records are
+  /// rejected without waiting again.
+  async fn push(&mut self, index: usize, record: Result<LogToolCallRequest, String>) {
+    let mut record = match record.and_then(validate) {
+      Ok(record) => record,
+      Err(error) => {
+        self.rejected.push(LogRecordError { index, error });
+        return;
+      }
+    };
+
+    {
+      let app_data = self.state.read().await;
+      if let Err(error) = check_catalog(&app_data, &mut record) {
+        self.rejected.push(LogRecordError { index, error });
+        return;
+      }
+      touch_record_batch(&app_data, &record);
+    }
+
+    let queued = if self.queue_full {
+      self.log_writer.try_enqueue(record)
+    } else {
+      self.log_writer.enqueue(record).await
+    };
+
+    match queued {
+      Ok(()) => self.accepted += 1,
+      Err(_) => {
+        self.queue_full = true;
+        self.rejected.push(LogRecordError {
+          index,
+          error: "log queue is full".to_string(),
+        });
+      }
+    }
+  }
+
+  fn into_response(self) -> LogBatchResponse {
+    LogBatchResponse {
+      accepted: self.accepted,
+      rejected: self.rejected,
+    }
+  }
+}
+
+fn validate(record: LogToolCallRequest) -> Result<LogToolCallRequest, String> {
+  if record.tool_name.is_empty() {
+    return Err("tool_name is empty".to_string());
+  }
+  if record.mcp_url.is_empty() {
+    return Err("mcp_url is empty".to_string());
+  }
+  if record
+    .features
+    .as_ref()
+    .is_some_and(|features| features.len() > MAX_TOOL_CALL_FEATURES)
+  {
+    return Err(format!("at most {} features are supported", MAX_TOOL_CALL_FEATURES));
+  }
+  Ok(record)
+}
+
+/// Attributes `record` to its batch and server. Without a batch the record is rejected if
+/// `LOG_REQUIRE_BATCH_ID` is set. A batch that is unknown, expired or does not include the
+/// server, a tool the server did not list, or a server no batch includes are rejected or
+/// flagged according to `LOG_UNKNOWN_TOOLS`; batch mismatches are always rejected when
+/// `LOG_REQUIRE_BATCH_ID` is set. Tools of servers whose catalog is not listed yet are accepted.
+fn check_catalog(app_data: &AppData, record: &mut LogToolCallRequest) -> Result<(), String> {
+  let validation = app_data.log_validation;
+
+  let batch_error = match &record.batch_id {
+    Some(batch_id) => match app_data.batch_map.get(batch_id) {
+      Some(batch) if batch.urls.contains(&record.mcp_url) => None,
+      Some(_) => Some(format!("{} is not registered under batch {}", record.mcp_url, batch_id)),
+      None => Some(format!("unknown batch {}", batch_id)),
+    },
+    None if validation.require_batch_id => return Err("batch_id is required".to_string()),
+    None => None,
+  };
+
+  if let Some(error) = batch_error {
+    if validation.require_batch_id || validation.unknown_tools == UnknownToolPolicy::Reject {
+      return Err(error);
+    }
+    record.unknown_batch = true;
+  }
+
+  if Catalog::of(app_data, &record.mcp_url).lacks(&record.tool_name) {
+    if validation.unknown_tools == UnknownToolPolicy::Reject {
+      return Err(format!("{} does not offer a tool named {}", record.mcp_url, record.tool_name));
+    }
+    record.unknown_tool = true;
+  }
+
+  Ok(())
+}
+
+/// What is known about the tools of a server `/log` is told about.
+#[derive(Debug)]
+enum Catalog<'a> {
+  /// Neither monitored nor part of any batch.
+  Unknown,
+  /// Part of a batch, but not monitored yet because it is being restored.
+  Pending,
+  /// Monitored. The tools are empty while it reconnects or if listing them failed.
+  Listed(&'a HashSet<String>),
+}
+
+impl<'a> Catalog<'a> {
+  fn of(app_data: &'a AppData, mcp_url: &str) -> Self {
+    match app_data.servers.get(mcp_url) {
+      Some(status) => Catalog::Listed(&status.tools),
+      None if app_data.batch_map.values().any(|batch| batch.urls.contains(mcp_url)) => Catalog::Pending,
+      None => Catalog::Unknown,
+    }
+  }
 
-  let result = sqlx::query!(
+  /// Whether the server is known not to offer `tool_name`. Pending or empty catalogs cannot
+  /// tell, so they accept every tool.
+  fn lacks(&self, tool_name: &str) -> bool {
+    match self {
+      Catalog::Unknown => true,
+      Catalog::Pending => false,
+      Catalog::Listed(tools) => !tools.is_empty() && !tools.contains(tool_name),
+    }
+  }
+}
+
+/// Records activity on the batch of `record`. Only the in-memory time is updated here; the log
+/// writer persists it together with the record.
+fn touch_record_batch(app_data: &AppData, record: &LogToolCallRequest) {
+  if record.unknown_batch {
+    return;
+  }
+  if let Some(batch) = record.batch_id.as_ref().and_then(|id| app_data.batch_map.get(id)) {
+    batch.touch();
+  }
+}
+
+/// Reads all of `body`. Fails with 413 once it grows past `limit`, and with 400 if it cannot be
+/// read, for example because the client went away halfway through.
+async fn read_body(body: Body, limit: usize) -> Result<Vec<u8>, StatusCode> {
+  let mut stream = body.into_data_stream();
+  let mut bytes = Vec::new();
+
+  while let Some(chunk) = stream.next().await {
+    let chunk = chunk.map_err(|e| {
+      error!(error = %e, "Failed to read tool calls");
+      StatusCode::BAD_REQUEST
+    })?;
+    if bytes.len() + chunk.len() > limit {
+      warn!(limit, "Tool calls body too large");
+      return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    bytes.extend_from_slice(&chunk);
+  }
+
+  Ok(bytes)
+}
+
+/// Queues the records of an NDJSON body as its lines arrive. Fails with the status to answer
+/// if the body cannot be read or a line exceeds `MAX_NDJSON_LINE_BYTES`.
+async fn ingest_ndjson(body: Body, ingest: &mut BatchIngest) -> Result<(), StatusCode> {
+  let mut stream = body.into_data_stream();
+  let mut buffer: Vec<u8> = Vec::new();
+  let mut index = 0;
+
+  while let Some(chunk) = stream.next().await {
+    let chunk = chunk.map_err(|e| {
+      error!(accepted = ingest.accepted, error = %e, "Failed to read tool calls");
+      StatusCode::BAD_REQUEST
+    })?;
+    buffer.extend_from_slice(&chunk);
+
+    let mut consumed = 0;
+    while let Some(newline) = buffer[consumed..].iter().position(|b| *b == b'\n') {
+      check_line_length(ingest, index, newline)?;
+      push_line(ingest, index, &buffer[consumed..consumed + newline]).await;
+      index += 1;
+      consumed += newline + 1;
+    }
+    buffer.drain(..consumed);
+    check_line_length(ingest, index, buffer.len())?;
+  }
+
+  push_line(ingest, index, &buffer).await;
+
+  Ok(())
+}
+
+fn check_line_length(ingest: &BatchIngest, index: usize, length: usize) -> Result<(), StatusCode> {
+  if length > MAX_NDJSON_LINE_BYTES {
+    warn!(accepted = ingest.accepted, line = index, "Tool call line too long");
+    return Err(StatusCode::PAYLOAD_TOO_LARGE);
+  }
+  Ok(())
+}
+
+async fn push_line(ingest: &mut BatchIngest, index: usize, line: &[u8]) {
+  let line = line.trim_ascii();
+  if !line.is_empty() {
+    ingest.push(index, serde_json::from_slice(line).map_err(|e| e.to_string())).await;
+  }
+}
+
+/// Stores `records` with a single multi-row insert, priced at the current `tool_prices`, adds
+/// their prices to what their batches spent and reports them to Prometheus. Calls to tools
+/// outside the catalog are not reported, so agents cannot create arbitrary label values.
+/// Called by the log writer. The insert and the batch updates share a transaction, so a
+/// retry after an error neither records nor charges calls twice.
+pub(crate) async fn insert_tool_calls(pool: &PgPool, records: &[LogToolCallRequest]) -> Result<()> {
+  let urls: Vec<String> = records
+    .iter()
+    .map(|record| record.mcp_url.clone())
+    .collect::<HashSet<_>>()
+    .into_iter()
+    .collect();
+  let prices = match load_prices(pool, &urls).await {
+    Ok(prices) => prices,
+    Err(e) => {
+      warn!(error = %e, "Failed to load prices, logging tool calls unpriced");
+      Default::default()
+    }
+  };
+
+  let mut tool_names = Vec::with_capacity(records.len());
+  let mut mcp_urls = Vec::with_capacity(records.len());
+  let mut total_times_ms = Vec::with_capacity(records.len());
+  let mut is_errors = Vec::with_capacity(records.len());
+  let mut batch_ids = Vec::with_capacity(records.len());
+  let mut session_ids = Vec::with_capacity(records.len());
+  let mut request_bytes = Vec::with_capacity(records.len());
+  let mut response_bytes = Vec::with_capacity(records.len());
+  let mut response_tokens = Vec::with_capacity(records.len());
+  let mut error_categories = Vec::with_capacity(records.len());
+  let mut mcp_error_codes = Vec::with_capacity(records.len());
+  let mut times_to_first_byte_ms = Vec::with_capacity(records.len());
+  let mut attributes = Vec::with_capacity(records.len());
+  let mut unknown_tools = Vec::with_capacity(records.len());
+  let mut unknown_batches = Vec::with_capacity(records.len());
+  let mut charged_batch_ids = Vec::with_capacity(records.len());
+  let mut features = Vec::with_capacity(records.len());
+  let mut call_prices = Vec::with_capacity(records.len());
+  for record in records {
+    tool_names.push(record.tool_name.clone());
+    mcp_urls.push(record.mcp_url.clone());
+    total_times_ms.push(record.total_time_ms as i64);
+    is_errors.push(record.is_error);
+    batch_ids.push(record.batch_id.clone());
+    session_ids.push(record.session_id.clone());
+    request_bytes.push(record.request_bytes.map(|bytes| bytes as i64));
+    response_bytes.push(record.response_bytes.map(|bytes| bytes as i64));
+    response_tokens.push(record.response_tokens.map(|tokens| tokens as i64));
+    error_categories.push(record.error_category.map(|category| category.as_str().to_string()));
+    mcp_error_codes.push(record.mcp_error_code);
+    times_to_first_byte_ms.push(record.time_to_first_byte_ms.map(|ms| ms as i64));
+    attributes.push(record.attributes.clone().map(serde_json::Value::Object));
+    unknown_tools.push(record.unknown_tool);
+    unknown_batches.push(record.unknown_batch);
+    charged_batch_ids.push(record.batch_id.clone().filter(|_| !record.unknown_batch));
+    features.push(record.features.as_ref().and_then(|features| serde_json::to_value(features).ok()));
+    call_prices.push(prices.get(&record.mcp_url, &record.tool_name).map(|price| {
+      let output_tokens = record
+        .response_tokens
+        .map(|tokens| tokens as f64)
+        .or(record.response_bytes.map(|bytes| bytes as f64 / BYTES_PER_TOKEN));
+      price.of_call(record.total_time_ms as f64, output_tokens)
+    }));
+  }
+
+  let mut tx = pool.begin().await?;
+
+  sqlx::query!(
     r#"
-    INSERT INTO tool_call_results (tool_name, mcp_url, total_time_ms, is_error)
-    VALUES ($1, $2, $3, $4)
+    INSERT INTO tool_call_results (
+      tool_name, mcp_url, total_time_ms, is_error, batch_id, session_id, request_bytes, response_bytes,
+      error_category, mcp_error_code, time_to_first_byte_ms, attributes, unknown_tool, features, response_tokens,
+      price, unknown_batch, source
+    )
+    SELECT *, $18
+    FROM UNNEST(
+      $1::TEXT[], $2::TEXT[], $3::BIGINT[], $4::BOOLEAN[], $5::TEXT[], $6::TEXT[], $7::BIGINT[], $8::BIGINT[],
+      $9::TEXT[], $10::INTEGER[], $11::BIGINT[], $12::JSONB[], $13::BOOLEAN[], $14::JSONB[], $15::BIGINT[],
+      $16::FLOAT8[], $17::BOOLEAN[]
+    )
     "#,
-    payload.tool_name,
-    payload.mcp_url,
-    payload.total_time_ms as i64,
-    payload.is_error
+    &tool_names,
+    &mcp_urls,
+    &total_times_ms,
+    &is_errors,
+    &batch_ids as &[Option<String>],
+    &session_ids as &[Option<String>],
+    &request_bytes as &[Option<i64>],
+    &response_bytes as &[Option<i64>],
+    &error_categories as &[Option<String>],
+    &mcp_error_codes as &[Option<i32>],
+    &times_to_first_byte_ms as &[Option<i64>],
+    &attributes as &[Option<serde_json::Value>],
+    &unknown_tools,
+    &features as &[Option<serde_json::Value>],
+    &response_tokens as &[Option<i64>],
+    &call_prices as &[Option<f64>],
+    &unknown_batches,
+    AGENT_SOURCE
   )
-  .execute(pool)
-  .await;
+  .execute(&mut *tx)
+  .await?;
 
-  println!(
-    "Logged call to {} from {} (Error: {}): {}ms",
-    payload.tool_name, payload.mcp_url, payload.is_error, payload.total_time_ms
-  );
+  if call_prices.iter().any(Option::is_some) {
+    sqlx::query!(
+      r#"
+      UPDATE batches
+      SET spent = spent + calls.price
+      FROM (
+        SELECT batch_id, SUM(price) AS price
+        FROM UNNEST($1::TEXT[], $2::FLOAT8[]) AS calls(batch_id, price)
+        WHERE batch_id IS NOT NULL
+        GROUP BY batch_id
+      ) AS calls
+      WHERE batches.id = calls.batch_id AND calls.price IS NOT NULL
+      "#,
+      &charged_batch_ids as &[Option<String>],
+      &call_prices as &[Option<f64>]
+    )
+    .execute(&mut *tx)
+    .await?;
+  }
 
-  match result {
-    Ok(_) => StatusCode::CREATED.into_response(),
-    Err(e) => {
-      eprintln!("Failed to log tool call: {}", e);
-      StatusCode::INTERNAL_SERVER_ERROR.into_response()
+  let mut active_batches: Vec<String> = charged_batch_ids.iter().flatten().cloned().collect();
+  active_batches.sort_unstable();
+  active_batches.dedup();
+
+  sqlx::query!("UPDATE batches SET last_activity = NOW() WHERE id = ANY($1)", &active_batches)
+    .execute(&mut *tx)
+    .await?;
+
+  tx.commit().await?;
+
+  for record in records.iter().filter(|record| !record.unknown_tool) {
+    record_tool_call(
+      &record.tool_name,
+      &record.mcp_url,
+      Duration::from_millis(record.total_time_ms),
+      record.is_error,
+      AGENT_SOURCE,
+    );
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+
+  use chrono::TimeDelta;
+  use metrics_exporter_prometheus::PrometheusBuilder;
+  use serde_json::Value;
+  use tokio::sync::{RwLock, mpsc};
+
+  use super::*;
+  use crate::{batches::Batch, memory_store::MemoryVectorStore};
+
+  const SERVER: &str = "http://server";
+
+  type Chunks = Vec<Result<String, &'static str>>;
+  /// Accepted records and rejected indices of a response with a body.
+  type Counts = Option<(u64, Vec<u64>)>;
+
+  /// State whose only batch includes `SERVER`, with a log queue nothing drains.
+  fn test_state(queue_capacity: usize) -> (AppState, mpsc::Receiver<LogToolCallRequest>) {
+    let (log_writer, queue) = LogWriter::stalled(queue_capacity);
+    let batch = Batch::new([SERVER.to_string()].into(), None, None);
+
+    let state = AppState::new(RwLock::new(AppData {
+      servers: HashMap::new(),
+      batch_map: [("batch".to_string(), batch)].into(),
+      vector_store: Arc::new(MemoryVectorStore::new()),
+      embedding_model: String::new(),
+      embedding_template: String::new(),
+      pool: PgPool::connect_lazy("postgres://localhost/unused").unwrap(),
+      metrics_handle: PrometheusBuilder::new().build_recorder().handle(),
+      tool_call_retention: TimeDelta::zero(),
+      log_writer,
+      log_validation: LogValidation {
+        unknown_tools: UnknownToolPolicy::Reject,
+        require_batch_id: false,
+      },
+    }));
+
+    (state, queue)
+  }
+
+  fn call(mcp_url: &str) -> String {
+    format!(r#"{{"tool_name": "search", "mcp_url": "{mcp_url}", "total_time_ms": 5, "is_error": false}}"#)
+  }
+
+  /// Posts `chunks` to `log_tool_calls`.
+  async fn post(state: AppState, content_type: &str, chunks: Chunks) -> (StatusCode, Counts) {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, content_type.parse().unwrap());
+    let body = Body::from_stream(futures::stream::iter(chunks));
+
+    let response = log_tool_calls(State(state), headers, body).await.into_response();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+    let counts = serde_json::from_slice::<Value>(&bytes).ok().map(|body| {
+      let rejected = body["rejected"].as_array().unwrap();
+      (
+        body["accepted"].as_u64().unwrap(),
+        rejected.iter().map(|error| error["index"].as_u64().unwrap()).collect(),
+      )
+    });
+
+    (status, counts)
+  }
+
+  #[tokio::test]
+  async fn log_tool_calls_cases() {
+    const JSON: &str = "application/json";
+    const NDJSON: &str = "application/x-ndjson";
+
+    let ok = call(SERVER);
+    let unknown_server = call("http://unknown");
+    let (head, tail) = ok.split_at(ok.len() / 2);
+    let long_line = "x".repeat(MAX_NDJSON_LINE_BYTES + 1);
+    let large_array = format!("[{}]", " ".repeat(LOG_BATCH_MAX_BODY_BYTES));
+
+    let cases: Vec<(&str, &str, Chunks, StatusCode, Counts)> = vec![
+      (
+        "json array reports rejected records by index",
+        JSON,
+        vec![Ok(format!(r#"[{ok}, {{"tool_name": "search"}}, {unknown_server}]"#))],
+        StatusCode::OK,
+        Some((1, vec![1, 2])),
+      ),
+      (
+        "json body that is not an array",
+        JSON,
+        vec![Ok(ok.clone())],
+        StatusCode::BAD_REQUEST,
+        None,
+      ),
+      (
+        "json body too large",
+        JSON,
+        vec![Ok(large_array)],
+        StatusCode::PAYLOAD_TOO_LARGE,
+        None,
+      ),
+      (
+        "json body cut off",
+        JSON,
+        vec![Ok(format!("[{ok}, ")), Err("connection reset")],
+        StatusCode::BAD_REQUEST,
+        None,
+      ),
+      (
+        "ndjson lines split across chunks, counting blank lines",
+        NDJSON,
+        vec![Ok(format!("{ok}\nnot json\n{head}")), Ok(format!("{tail}\n\n{unknown_server}"))],
+        StatusCode::OK,
+        Some((2, vec![1, 4])),
+      ),
+      (
+        "ndjson line too long keeps earlier records",
+        NDJSON,
+        vec![Ok(format!("{ok}\n")), Ok(long_line), Ok(format!("\n{ok}"))],
+        StatusCode::PAYLOAD_TOO_LARGE,
+        Some((1, vec![])),
+      ),
+      (
+        "ndjson body cut off keeps earlier records",
+        NDJSON,
+        vec![Ok(format!("{ok}\n{head}")), Err("connection reset")],
+        StatusCode::BAD_REQUEST,
+        Some((1, vec![])),
+      ),
+    ];
+
+    for (name, content_type, chunks, status, counts) in cases {
+      let (state, _queue) = test_state(16);
+      assert_eq!(post(state, content_type, chunks).await, (status, counts), "{name}");
+    }
+  }
+
+  #[tokio::test]
+  async fn log_tool_calls_full_queue() {
+    let (state, mut queue) = test_state(1);
+    let body = [call(SERVER), call(SERVER), call(SERVER)].join("\n");
+
+    let response = post(state, "application/x-ndjson", vec![Ok(body)]).await;
+
+    assert_eq!(response, (StatusCode::SERVICE_UNAVAILABLE, Some((1, vec![1, 2]))));
+    assert!(queue.try_recv().is_ok());
+  }
+
+  #[test]
+  fn catalog_lacks_tool() {
+    let listed: HashSet<String> = ["search".to_string()].into();
+    let empty = HashSet::new();
+
+    let cases = [
+      ("listed tool", Catalog::Listed(&listed), "search", false),
+      ("unlisted tool", Catalog::Listed(&listed), "fetch", true),
+      ("catalog empty after a failed listing", Catalog::Listed(&empty), "fetch", false),
+      ("server being restored", Catalog::Pending, "fetch", false),
+      ("server not registered", Catalog::Unknown, "search", true),
+    ];
+
+    for (name, catalog, tool_name, lacks) in cases {
+      assert_eq!(catalog.lacks(tool_name), lacks, "{name}");
     }
   }
 }
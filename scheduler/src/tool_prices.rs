@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use axum::{
+  Json,
+  extract::{Path, State},
+  http::StatusCode,
+  response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::{error, info, instrument};
+use uuid::Uuid;
+
+use crate::types::AppState;
+
+/// What `per_unit` of a price is charged for.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum PriceUnit {
+  /// Tokens of output, estimated from its size in bytes when a call does not report them.
+  OutputToken,
+  /// Seconds the call took.
+  Second,
+}
+
+impl PriceUnit {
+  fn as_str(self) -> &'static str {
+    match self {
+      PriceUnit::OutputToken => "output_token",
+      PriceUnit::Second => "second",
+    }
+  }
+
+  fn parse(unit: &str) -> Option<Self> {
+    match unit {
+      "output_token" => Some(PriceUnit::OutputToken),
+      "second" => Some(PriceUnit::Second),
+      _ => None,
+    }
+  }
+}
+
+/// Prices every call of `tool_name` on `mcp_url`, or of every tool on it without a price of
+/// its own when `tool_name` is omitted. Prices are in whatever currency batch budgets use.
+#[derive(Deserialize)]
+pub(crate) struct ToolPriceRequest {
+  pub(crate) mcp_url: String,
+  pub(crate) tool_name: Option<String>,
+  pub(crate) per_call: Option<f64>,
+  pub(crate) per_unit: Option<f64>,
+  pub(crate) unit: Option<PriceUnit>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct ToolPrice {
+  pub(crate) id: Uuid,
+  pub(crate) mcp_url: String,
+  pub(crate) tool_name: Option<String>,
+  pub(crate) per_call: f64,
+  pub(crate) per_unit: f64,
+  pub(crate) unit: Option<PriceUnit>,
+}
+
+impl ToolPrice {
+  /// Price of a call that took `latency_ms` and returned `output_tokens`. A missing output
+  /// size counts as none.
+  pub(crate) fn of_call(&self, latency_ms: f64, output_tokens: Option<f64>) -> f64 {
+    let units = match self.unit {
+      Some(PriceUnit::OutputToken) => output_tokens.unwrap_or_default(),
+      Some(PriceUnit::Second) => latency_ms / 1000.0,
+      None => 0.0,
+    };
+    self.per_call + self.per_unit * units
+  }
+}
+
+/// The prices of some servers, looked up by tool.
+#[derive(Default)]
+pub(crate) struct Prices {
+  prices: HashMap<(String, Option<String>), ToolPrice>,
+}
+
+impl Prices {
+  pub(crate) fn get(&self, mcp_url: &str, tool_name: &str) -> Option<&ToolPrice> {
+    self
+      .prices
+      .get(&(mcp_url.to_string(), Some(tool_name.to_string())))
+      .or_else(|| self.prices.get(&(mcp_url.to_string(), None)))
+  }
+}
+
+/// Loads the prices configured for `mcp_urls`.
+pub(crate) async fn load_prices(pool: &PgPool, mcp_urls: &[String]) -> Result<Prices> {
+  let prices = query_prices(pool, Some(mcp_urls))
+    .await?
+    .into_iter()
+    .map(|price| ((price.mcp_url.clone(), price.tool_name.clone()), price))
+    .collect();
+
+  Ok(Prices { prices })
+}
+
+async fn query_prices(pool: &PgPool, mcp_urls: Option<&[String]>) -> Result<Vec<ToolPrice>> {
+  let rows = sqlx::query!(
+    r#"
+    SELECT id, mcp_url, tool_name, per_call, per_unit, unit
+    FROM tool_prices
+    WHERE $1::TEXT[] IS NULL OR mcp_url = ANY($1)
+    ORDER BY mcp_url, tool_name NULLS FIRST
+    "#,
+    mcp_urls
+  )
+  .fetch_all(pool)
+  .await?;
+
+  Ok(
+    rows
+      .into_iter()
+      .map(|row| ToolPrice {
+        id: row.id,
+        mcp_url: row.mcp_url,
+        tool_name: row.tool_name,
+        per_call: row.per_call,
+        per_unit: row.per_unit,
+        unit: row.unit.as_deref().and_then(PriceUnit::parse),
+      })
+      .collect(),
+  )
+}
+
+/// Creates a price, or replaces the one already defined for the same tool or server. Calls
+/// logged before keep the price they were logged at.
+#[instrument(skip_all, fields(mcp_url = %payload.mcp_url, tool_name = ?payload.tool_name))]
+pub(crate) async fn put_price(State(state): State<AppState>, Json(payload): Json<ToolPriceRequest>) -> impl IntoResponse {
+  if payload.per_unit.is_some() != payload.unit.is_some() {
+    return (StatusCode::BAD_REQUEST, "per_unit and unit must be set together").into_response();
+  }
+  let amounts = [("per_call", payload.per_call), ("per_unit", payload.per_unit)];
+  if let Some((name, _)) = amounts
+    .iter()
+    .find(|(_, amount)| amount.is_some_and(|amount| !amount.is_finite() || amount < 0.0))
+  {
+    return (StatusCode::BAD_REQUEST, format!("{} must be a non-negative number", name)).into_response();
+  }
+
+  let pool = state.read().await.pool.clone();
+
+  let result = sqlx::query!(
+    r#"
+    INSERT INTO tool_prices (mcp_url, tool_name, per_call, per_unit, unit)
+    VALUES ($1, $2, $3, $4, $5)
+    ON CONFLICT (mcp_url, tool_name) DO UPDATE SET
+      per_call = EXCLUDED.per_call,
+      per_unit = EXCLUDED.per_unit,
+      unit = EXCLUDED.unit
+    RETURNING id
+    "#,
+    payload.mcp_url,
+    payload.tool_name,
+    payload.per_call.unwrap_or_default(),
+    payload.per_unit.unwrap_or_default(),
+    payload.unit.map(PriceUnit::as_str)
+  )
+  .fetch_one(&pool)
+  .await;
+
+  match result {
+    Ok(row) => {
+      info!(per_call = payload.per_call, per_unit = payload.per_unit, "Price saved");
+      let price = ToolPrice {
+        id: row.id,
+        mcp_url: payload.mcp_url,
+        tool_name: payload.tool_name,
+        per_call: payload.per_call.unwrap_or_default(),
+        per_unit: payload.per_unit.unwrap_or_default(),
+        unit: payload.unit,
+      };
+      (StatusCode::CREATED, Json(Some(price))).into_response()
+    }
+    Err(e) => {
+      error!(error = %e, "Failed to save price");
+      (StatusCode::INTERNAL_SERVER_ERROR, Json(None::<ToolPrice>)).into_response()
+    }
+  }
+}
+
+#[instrument(skip_all)]
+pub(crate) async fn list_prices(State(state): State<AppState>) -> impl IntoResponse {
+  let pool = state.read().await.pool.clone();
+
+  match query_prices(&pool, None).await {
+    Ok(prices) => (StatusCode::OK, Json(prices)).into_response(),
+    Err(e) => {
+      error!(error = %e, "Failed to list prices");
+      (StatusCode::INTERNAL_SERVER_ERROR, Json(Vec::<ToolPrice>::new())).into_response()
+    }
+  }
+}
+
+#[instrument(skip(state))]
+pub(crate) async fn delete_price(State(state): State<AppState>, Path(id): Path<Uuid>) -> impl IntoResponse {
+  let pool = state.read().await.pool.clone();
+
+  match sqlx::query!("DELETE FROM tool_prices WHERE id = $1", id).execute(&pool).await {
+    Ok(result) if result.rows_affected() == 0 => StatusCode::NOT_FOUND,
+    Ok(_) => StatusCode::NO_CONTENT,
+    Err(e) => {
+      error!(error = %e, "Failed to delete price");
+      StatusCode::INTERNAL_SERVER_ERROR
+    }
+  }
+}
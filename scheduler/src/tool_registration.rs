@@ -1,25 +1,28 @@
-use std::{
-  collections::{HashMap, HashSet},
-  sync::Arc,
-  time::Instant,
-};
+use std::{collections::HashSet, sync::Arc};
 
 use anyhow::Result;
 use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
-use qdrant_client::qdrant::{CreateCollection, Distance, PointStruct, VectorParams, VectorsConfig};
 use rmcp::{
   ServiceExt,
-  model::{ClientCapabilities, ClientInfo, Implementation, ProtocolVersion},
+  model::{ClientCapabilities, ClientInfo, Implementation, ProtocolVersion, Tool},
   transport::StreamableHttpClientTransport,
 };
+use tracing::{Span, error, field, info, instrument, warn};
 use uuid::Uuid;
 
 use crate::{
-  MAX_PING_HISTORY, QDRANT_COLLECTION_NAME,
-  embeddings::generate_embedding,
+  MAX_PING_HISTORY, TOOL_COLLECTION_NAME,
+  batches::{Batch, delete_batches, persist_batch},
+  collections::DESCRIPTION_VECTOR,
+  embedding_template::{ToolDescriptor, embed_tool},
+  health::ServerHealth,
+  prometheus_metrics::record_registration,
+  reconnect::ReconnectState,
   types::{AppState, DynamicMcpClient, RegisterRequest, RegisterResponse, ServerStatus, UnregisterRequest, UnregisterResponse},
+  vector_store::{PayloadFilter, ToolPoint, VectorStore},
 };
 
+#[instrument(skip_all, fields(batch_id = field::Empty))]
 pub(crate) async fn register_server(State(state): State<AppState>, Json(payload): Json<RegisterRequest>) -> impl IntoResponse {
   if payload.mcp_urls.is_empty() {
     return (
@@ -28,66 +31,83 @@ pub(crate) async fn register_server(State(state): State<AppState>, Json(payload)
         message: "No URLs provided for registration.".to_string(),
         registered_id: None,
         urls: Vec::new(),
+        ttl_seconds: None,
+      }),
+    )
+      .into_response();
+  }
+
+  if payload.budget.is_some_and(|budget| !budget.is_finite() || budget < 0.0) {
+    return (
+      StatusCode::BAD_REQUEST,
+      Json(RegisterResponse {
+        message: "budget must be a non-negative number.".to_string(),
+        registered_id: None,
+        urls: Vec::new(),
+        ttl_seconds: None,
+      }),
+    )
+      .into_response();
+  }
+
+  if payload.ttl_seconds == Some(0) {
+    return (
+      StatusCode::BAD_REQUEST,
+      Json(RegisterResponse {
+        message: "ttl_seconds must be positive.".to_string(),
+        registered_id: None,
+        urls: Vec::new(),
+        ttl_seconds: None,
       }),
     )
       .into_response();
   }
 
   let batch_id = Uuid::new_v4().to_string();
-  let registration_time = Instant::now();
   let mut urls_in_batch = HashSet::new();
   let mut successfully_registered_urls = Vec::new();
 
   let mut app_data = state.write().await;
-  let qdrant = app_data.qdrant.clone();
+  let vector_store = app_data.vector_store.clone();
+  let embedding_model = app_data.embedding_model.clone();
+  let embedding_template = app_data.embedding_template.clone();
   let servers = &mut app_data.servers;
 
-  println!("Creating new registration {}", batch_id);
+  Span::current().record("batch_id", &batch_id);
+  info!(urls = ?payload.mcp_urls, "Creating new registration");
 
   for url in &payload.mcp_urls {
     if let Some(status) = servers.get_mut(url) {
-      status.active_batches.insert(batch_id.clone(), registration_time);
-      println!("New registration (ID: {}) for existing server: {}", batch_id, url);
+      status.active_batches.insert(batch_id.clone());
+      info!(mcp_url = %url, "New registration for existing server");
     } else {
-      println!("Registering new server: {}", url);
-
-      let client_info = ClientInfo {
-        protocol_version: ProtocolVersion::default(),
-        capabilities: ClientCapabilities::default(),
-        client_info: Implementation {
-          name: "heartbeat-monitor".to_string(),
-          version: "0.1.0".to_string(),
-          title: Some("MCP Heartbeat Monitor".to_string()),
-          icons: None,
-          website_url: None,
-        },
-      };
-
-      let transport = StreamableHttpClientTransport::from_uri(url.clone());
-
-      let client_result = client_info.serve(transport).await;
+      info!(mcp_url = %url, "Registering new server");
 
-      match client_result {
+      match connect_client(url).await {
         Ok(client) => {
-          let client = Arc::new(client);
-
-          if let Err(e) = fetch_and_store_tools(&client, url, &qdrant).await {
-            eprintln!("Failed to fetch/store tools for {}: {}", url, e);
-          }
+          let tools = match fetch_and_store_tools(&client, url, vector_store.as_ref(), &embedding_model, &embedding_template).await {
+            Ok(tools) => tools,
+            Err(e) => {
+              error!(mcp_url = %url, error = %e, "Failed to fetch/store tools");
+              HashSet::new()
+            }
+          };
 
-          let mut active_batches = HashMap::new();
-          active_batches.insert(batch_id.clone(), registration_time);
+          let active_batches = HashSet::from([batch_id.clone()]);
 
           let status = ServerStatus {
             client,
             active_batches,
+            tools,
             latency_history: Vec::with_capacity(MAX_PING_HISTORY),
+            health: ServerHealth::default(),
+            reconnect: ReconnectState::default(),
           };
 
           servers.insert(url.clone(), status);
         }
         Err(e) => {
-          eprintln!("Failed to start client for {}: {:?}", url, e);
+          warn!(mcp_url = %url, error = %e, "Failed to start client");
           continue;
         }
       }
@@ -104,12 +124,17 @@ pub(crate) async fn register_server(State(state): State<AppState>, Json(payload)
         message: "All URLs failed to start their MCP client runtime.".to_string(),
         registered_id: None,
         urls: vec![],
+        ttl_seconds: None,
       }),
     )
       .into_response();
   }
 
-  app_data.batch_map.insert(batch_id.clone(), urls_in_batch);
+  let batch = Batch::new(urls_in_batch, payload.ttl_seconds, payload.budget);
+  let ttl_seconds = batch.ttl.as_secs();
+  persist_batch(&app_data.pool, &batch_id, &batch).await;
+  record_registration(batch.urls.len());
+  app_data.batch_map.insert(batch_id.clone(), batch);
 
   (
     StatusCode::CREATED,
@@ -121,18 +146,23 @@ pub(crate) async fn register_server(State(state): State<AppState>, Json(payload)
       ),
       registered_id: Some(batch_id),
       urls: successfully_registered_urls,
+      ttl_seconds: Some(ttl_seconds),
     }),
   )
     .into_response()
 }
 
+#[instrument(skip_all, fields(batch_id = %payload.registration_id))]
 pub(crate) async fn unregister_server(State(state): State<AppState>, Json(payload): Json<UnregisterRequest>) -> impl IntoResponse {
   let batch_id = payload.registration_id;
   let mut app_data = state.write().await;
   let mut urls_stopped_monitoring = 0;
 
   let urls_in_batch = match app_data.batch_map.remove(&batch_id) {
-    Some(urls) => urls,
+    Some(batch) => {
+      delete_batches(&app_data.pool, std::slice::from_ref(&batch_id)).await;
+      batch.urls
+    }
     None => {
       let response_body = UnregisterResponse {
         message: format!("Batch ID {} not found or already unregistered.", batch_id),
@@ -160,7 +190,7 @@ pub(crate) async fn unregister_server(State(state): State<AppState>, Json(payloa
 
   for url in urls_to_remove {
     if app_data.servers.remove(&url).is_some() {
-      println!("Monitoring stopped for {} as the last batch ID was unregistered.", url);
+      info!(mcp_url = %url, "Monitoring stopped, the last batch ID was unregistered");
     }
   }
 
@@ -179,172 +209,134 @@ pub(crate) async fn unregister_server(State(state): State<AppState>, Json(payloa
   (StatusCode::OK, Json(response_body)).into_response()
 }
 
-async fn ensure_collection_exists(qdrant: &qdrant_client::Qdrant, vector_size: u64) -> Result<()> {
-  let collection_name = QDRANT_COLLECTION_NAME;
-
-  let collections = qdrant.list_collections().await?;
-  let collection_exists = collections.collections.iter().any(|c| c.name == collection_name);
-
-  if !collection_exists {
-    println!("Creating Qdrant collection: {} with vector size: {}", collection_name, vector_size);
-    qdrant
-      .create_collection(CreateCollection {
-        collection_name: collection_name.to_string(),
-        vectors_config: Some(VectorsConfig {
-          config: Some(qdrant_client::qdrant::vectors_config::Config::Params(VectorParams {
-            size: vector_size,
-            distance: Distance::Cosine as i32,
-            ..Default::default()
-          })),
-        }),
-        ..Default::default()
-      })
-      .await?;
-    println!("Successfully created collection: {}", collection_name);
+/// Runs the MCP handshake with the server at `url`.
+#[instrument(skip_all, fields(mcp_url = %url))]
+pub(crate) async fn connect_client(url: &str) -> Result<DynamicMcpClient> {
+  let client_info = ClientInfo {
+    protocol_version: ProtocolVersion::default(),
+    capabilities: ClientCapabilities::default(),
+    client_info: Implementation {
+      name: "heartbeat-monitor".to_string(),
+      version: "0.1.0".to_string(),
+      title: Some("MCP Heartbeat Monitor".to_string()),
+      icons: None,
+      website_url: None,
+    },
+  };
+
+  let transport = StreamableHttpClientTransport::from_uri(url.to_string());
+
+  match client_info.serve(transport).await {
+    Ok(client) => Ok(Arc::new(client)),
+    Err(e) => Err(anyhow::anyhow!("Failed to start client: {:?}", e)),
+  }
+}
+
+/// Lists the tools of `mcp_url` and stores their embeddings. Returns the listed tool names,
+/// which make up the server's catalog for `/log`, even if storing the embeddings failed.
+#[instrument(skip_all, fields(mcp_url = %mcp_url))]
+pub(crate) async fn fetch_and_store_tools(
+  client: &DynamicMcpClient,
+  mcp_url: &str,
+  vector_store: &dyn VectorStore,
+  embedding_model: &str,
+  embedding_template: &str,
+) -> Result<HashSet<String>> {
+  let tools = list_tools(client).await?;
+
+  if let Err(e) = store_tools(&tools, mcp_url, vector_store, embedding_model, embedding_template).await {
+    error!(error = %e, "Failed to store tools");
   }
 
-  Ok(())
+  Ok(tools.iter().map(|tool| tool.name.to_string()).collect())
 }
 
-async fn fetch_and_store_tools(client: &DynamicMcpClient, mcp_url: &str, qdrant: &std::sync::Arc<qdrant_client::Qdrant>) -> Result<()> {
-  let tools_result = client.list_tools(Default::default()).await;
-  let tools = match tools_result {
-    Ok(tools_response) => tools_response.tools,
+pub(crate) async fn list_tools(client: &DynamicMcpClient) -> Result<Vec<Tool>> {
+  match client.list_tools(Default::default()).await {
+    Ok(tools_response) => Ok(tools_response.tools),
     Err(e) => {
-      eprintln!("Failed to list tools from {}: {:?}", mcp_url, e);
-      return Err(anyhow::anyhow!("Failed to list tools: {:?}", e));
+      error!(error = ?e, "Failed to list tools");
+      Err(anyhow::anyhow!("Failed to list tools: {:?}", e))
     }
-  };
+  }
+}
 
+/// Embeds `tools` and stores them under `mcp_url`. Callers hold the state's write lock, so an
+/// embedding migration cannot switch models while they store.
+pub(crate) async fn store_tools(
+  tools: &[Tool],
+  mcp_url: &str,
+  vector_store: &dyn VectorStore,
+  embedding_model: &str,
+  embedding_template: &str,
+) -> Result<()> {
   if tools.is_empty() {
-    println!("No tools found for MCP server: {}", mcp_url);
+    info!("No tools found");
     return Ok(());
   }
 
-  println!("Found {} tools from MCP server: {}", tools.len(), mcp_url);
-
-  let first_tool = &tools[0];
-  let first_tool_name = first_tool.name.clone().into_owned();
-  let first_tool_description = first_tool.description.clone().map(|d| d.into_owned()).unwrap_or_default();
-
-  let first_description_text = if first_tool_description.is_empty() {
-    first_tool_name.clone()
-  } else {
-    format!("{}: {}", first_tool_name, first_tool_description)
-  };
-
-  let first_embedding = generate_embedding(&first_description_text).await?;
-  let vector_size = first_embedding.len() as u64;
-
-  ensure_collection_exists(qdrant, vector_size).await?;
+  info!(tool_count = tools.len(), "Found tools");
 
   let mut points = Vec::new();
+  let mut collection_ready = false;
 
-  let point_id_str = format!("{}:{}", mcp_url, first_tool_name);
-  let point_id = Uuid::new_v5(&Uuid::NAMESPACE_URL, point_id_str.as_bytes()).to_string();
+  for tool in tools {
+    let descriptor = ToolDescriptor::from_tool(tool);
 
-  let mut payload_map = HashMap::new();
-  payload_map.insert(
-    "name".to_string(),
-    qdrant_client::qdrant::Value {
-      kind: Some(qdrant_client::qdrant::value::Kind::StringValue(first_tool_name.clone())),
-    },
-  );
-  payload_map.insert(
-    "description".to_string(),
-    qdrant_client::qdrant::Value {
-      kind: Some(qdrant_client::qdrant::value::Kind::StringValue(first_tool_description.clone())),
-    },
-  );
-  payload_map.insert(
-    "mcp_url".to_string(),
-    qdrant_client::qdrant::Value {
-      kind: Some(qdrant_client::qdrant::value::Kind::StringValue(mcp_url.to_string())),
-    },
-  );
-
-  let schema_json = serde_json::to_string(&first_tool.input_schema).unwrap_or_else(|_| "{}".to_string());
-  payload_map.insert(
-    "inputSchema".to_string(),
-    qdrant_client::qdrant::Value {
-      kind: Some(qdrant_client::qdrant::value::Kind::StringValue(schema_json)),
-    },
-  );
-
-  let point = PointStruct::new(point_id, first_embedding, payload_map);
-  points.push(point);
-
-  for tool in tools.iter().skip(1) {
-    let tool_name = tool.name.clone().into_owned();
-    let tool_description = tool.description.clone().map(|d| d.into_owned()).unwrap_or_default();
-
-    let description_text = if tool_description.is_empty() {
-      tool_name.clone()
-    } else {
-      format!("{}: {}", tool_name, tool_description)
-    };
-
-    let embedding = match generate_embedding(&description_text).await {
-      Ok(emb) => emb,
+    let (vectors, payload_map) = match embed_tool(&descriptor, mcp_url, embedding_template, embedding_model).await {
+      Ok(embedded) => embedded,
       Err(e) => {
-        eprintln!("Failed to generate embedding for tool {}: {}", tool_name, e);
+        error!(tool_name = %descriptor.name, error = %e, "Failed to generate embedding");
         continue;
       }
     };
 
-    let point_id_str = format!("{}:{}", mcp_url, tool_name);
-    let point_id = Uuid::new_v5(&Uuid::NAMESPACE_URL, point_id_str.as_bytes()).to_string();
-
-    let mut payload_map = HashMap::new();
-    payload_map.insert(
-      "name".to_string(),
-      qdrant_client::qdrant::Value {
-        kind: Some(qdrant_client::qdrant::value::Kind::StringValue(tool_name.clone())),
-      },
-    );
-    payload_map.insert(
-      "description".to_string(),
-      qdrant_client::qdrant::Value {
-        kind: Some(qdrant_client::qdrant::value::Kind::StringValue(tool_description.clone())),
-      },
-    );
-    payload_map.insert(
-      "mcp_url".to_string(),
-      qdrant_client::qdrant::Value {
-        kind: Some(qdrant_client::qdrant::value::Kind::StringValue(mcp_url.to_string())),
-      },
-    );
-
-    let schema_json = serde_json::to_string(&tool.input_schema).unwrap_or_else(|_| "{}".to_string());
-    payload_map.insert(
-      "inputSchema".to_string(),
-      qdrant_client::qdrant::Value {
-        kind: Some(qdrant_client::qdrant::value::Kind::StringValue(schema_json)),
-      },
-    );
-
-    let point = PointStruct::new(point_id, embedding, payload_map);
-
-    points.push(point);
+    if !collection_ready {
+      let vector_size = vectors.get(DESCRIPTION_VECTOR).map(|v| v.len()).unwrap_or_default() as u64;
+      vector_store.ensure_collection(embedding_model, vector_size).await?;
+      collection_ready = true;
+    }
+
+    points.push(ToolPoint {
+      id: tool_point_id(mcp_url, &descriptor.name),
+      vectors,
+      payload: payload_map,
+    });
   }
 
   if points.is_empty() {
-    println!("No valid points to insert for MCP server: {}", mcp_url);
+    warn!("No valid points to insert");
     return Ok(());
   }
 
   let points_count = points.len();
 
-  qdrant
-    .upsert_points(qdrant_client::qdrant::UpsertPoints {
-      collection_name: QDRANT_COLLECTION_NAME.to_string(),
-      wait: Some(true),
-      points,
-      ..Default::default()
-    })
-    .await?;
+  vector_store.upsert(TOOL_COLLECTION_NAME, points).await?;
 
-  println!("Successfully stored {} tool embeddings for MCP server: {}", points_count, mcp_url);
+  info!(tool_count = points_count, "Stored tool embeddings");
+
+  let listed_ids: HashSet<String> = tools.iter().map(|tool| tool_point_id(mcp_url, &tool.name)).collect();
+  let stale_ids: Vec<String> = vector_store
+    .scroll(
+      TOOL_COLLECTION_NAME,
+      Some(&PayloadFilter::matches("mcp_url", [mcp_url.to_string()])),
+      false,
+    )
+    .await?
+    .into_iter()
+    .map(|point| point.id)
+    .filter(|id| !listed_ids.contains(id))
+    .collect();
+
+  if !stale_ids.is_empty() {
+    info!(tool_count = stale_ids.len(), "Removing tools no longer offered by the server");
+    vector_store.delete(TOOL_COLLECTION_NAME, stale_ids).await?;
+  }
 
   Ok(())
 }
+
+fn tool_point_id(mcp_url: &str, tool_name: &str) -> String {
+  let point_id_str = format!("{}:{}", mcp_url, tool_name);
+  Uuid::new_v5(&Uuid::NAMESPACE_URL, point_id_str.as_bytes()).to_string()
+}
@@ -1,84 +1,297 @@
+use std::collections::{HashMap, HashSet};
+
 use axum::{Json, extract::Query, extract::State, http::StatusCode, response::IntoResponse};
 use futures::future::join_all;
-use qdrant_client::qdrant::{Condition, Filter, RetrievedPoint, ScrollPoints, VectorsOutput};
 use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::{debug, error, info, instrument, warn};
 
 use crate::{
-  CLUSTER_SIMILARITY_THRESHOLD, M_ERROR_WINDOW_MINUTES, MAX_TOOL_CALL_LOGS, N_ERROR_THRESHOLD, QDRANT_COLLECTION_NAME, types::AppState,
+  BYTES_PER_TOKEN, CLUSTER_SIMILARITY_THRESHOLD, DEADLINE_SAMPLES, DEFAULT_MIN_SIMILARITY, DEFAULT_OUTPUT_TOKEN_WEIGHT, DEFAULT_RTT_WEIGHT,
+  DEFAULT_TOOL_LIMIT, DESCRIPTION_VECTOR_WEIGHT, FEATURE_LATENCY_SAMPLES, M_ERROR_WINDOW_MINUTES, MAX_TOOL_CALL_FEATURES,
+  MAX_TOOL_CALL_LOGS, MIN_FEATURE_SAMPLES, N_ERROR_THRESHOLD, NAME_VECTOR_WEIGHT, OUTPUT_SIZE_SAMPLES, SCHEMA_VECTOR_WEIGHT,
+  TOOL_COLLECTION_NAME,
+  batches::{remaining_budget, touch_batch},
+  embeddings::generate_embedding_with_model,
+  health::HealthState,
+  prometheus_metrics::{record_search, record_selection},
+  routing_audit::{RoutingCandidate, RoutingDecision, RoutingMode, RoutingRequest, RoutingStrategy, record_decisions},
+  tool_prices::{PriceUnit, Prices, load_prices},
+  types::AppState,
+  utils::rtt_increase_ms,
+  vector_store::{PayloadFilter, ToolPoint, VectorStore, VectorWeights},
 };
 
 #[derive(Deserialize)]
 pub(crate) struct SearchToolsQuery {
   pub(crate) batch_id: String,
+  /// Restricts results to tools relevant to this text, ranked by similarity.
+  pub(crate) query: Option<String>,
+  pub(crate) limit: Option<usize>,
+  pub(crate) score_threshold: Option<f32>,
+  pub(crate) name_weight: Option<f32>,
+  pub(crate) description_weight: Option<f32>,
+  pub(crate) schema_weight: Option<f32>,
+  /// Share of the increase of the server's current ping round trip over its usual one added
+  /// to a tool's call latency when picking the fastest tool of a cluster. Defaults to
+  /// `DEFAULT_RTT_WEIGHT`.
+  pub(crate) rtt_weight: Option<f64>,
+  /// Milliseconds of latency one expected output token is worth, so tools returning less
+  /// text win over slightly faster ones. Defaults to `DEFAULT_OUTPUT_TOKEN_WEIGHT`.
+  pub(crate) output_token_weight: Option<f64>,
+  pub(crate) routing: Option<RoutingMode>,
+  /// JSON object of the intended call's argument features, like the ones logged with
+  /// `/log`. Used by input-aware routing.
+  pub(crate) features: Option<String>,
+  /// Expected latency, in milliseconds, the cheapest routing mode picks the cheapest tool
+  /// within. Without one it picks the cheapest tool of each cluster.
+  pub(crate) latency_target_ms: Option<f64>,
+  /// Milliseconds the caller can wait for the tool call. Tools whose p95 latency exceeds it
+  /// are left out, and the tool most likely to finish in time is picked rather than the
+  /// fastest on average. In the cheapest mode it only leaves tools out.
+  pub(crate) deadline_ms: Option<f64>,
 }
 
 #[derive(Serialize, Clone, Debug)]
 pub(crate) struct ToolResult {
   pub(crate) name: String,
   pub(crate) mcp_url: String,
+  pub(crate) score: Option<f32>,
+}
+
+/// What the routing cost of a cluster's members is based on besides their logged calls.
+struct CostInputs<'a> {
+  /// Increase of each server's current round trip over its usual one.
+  rtt_increases: &'a HashMap<String, f64>,
+  rtt_weight: f64,
+  output_token_weight: f64,
+  /// Argument features of the intended call, when routing is input-aware.
+  features: Option<&'a HashMap<String, String>>,
+  /// Prices of the batch's servers, when routing is budget-aware.
+  prices: Option<&'a Prices>,
+  deadline_ms: Option<f64>,
+}
+
+struct RoutingCost {
+  /// What the tool is ranked by.
+  cost_ms: f64,
+  /// Expected latency of a call, including the weighted round trip increase.
+  latency_ms: f64,
+  /// Expected price of a call, for priced tools when routing is budget-aware.
+  price: Option<f64>,
+  /// Chance that a call succeeds within the deadline, if there is one and the tool has history.
+  on_time_probability: Option<f64>,
+  /// p95 latency of successful calls, including the weighted round trip increase, if there is a
+  /// deadline and the tool has history.
+  p95_latency_ms: Option<f64>,
+}
+
+/// How likely a tool is to finish within a deadline.
+#[derive(Default)]
+struct DeadlineFit {
+  on_time_probability: Option<f64>,
+  p95_latency_ms: Option<f64>,
+}
+
+impl SearchToolsQuery {
+  /// Deadlines and latency targets must be positive, cost and vector weights non-negative; 0
+  /// switches a weight off, though not every vector weight at once.
+  fn validate(&self) -> Result<(), String> {
+    let durations = [("deadline_ms", self.deadline_ms), ("latency_target_ms", self.latency_target_ms)];
+    for (name, value) in durations {
+      if value.is_some_and(|value| !value.is_finite() || value <= 0.0) {
+        return Err(format!("{} must be a positive number", name));
+      }
+    }
+
+    let weights = [("rtt_weight", self.rtt_weight), ("output_token_weight", self.output_token_weight)];
+    for (name, value) in weights {
+      if value.is_some_and(|value| !value.is_finite() || value < 0.0) {
+        return Err(format!("{} must be a non-negative number", name));
+      }
+    }
+
+    let vector_weights = [
+      ("name_weight", self.name_weight),
+      ("description_weight", self.description_weight),
+      ("schema_weight", self.schema_weight),
+    ];
+    for (name, value) in vector_weights {
+      if value.is_some_and(|value| !value.is_finite() || value < 0.0) {
+        return Err(format!("{} must be a non-negative number", name));
+      }
+    }
+    if VectorWeights::from_query(self).iter().iter().all(|(_, weight)| *weight == 0.0) {
+      return Err("at least one of name_weight, description_weight and schema_weight must be positive".to_string());
+    }
+
+    Ok(())
+  }
+}
+
+impl VectorWeights {
+  fn from_query(params: &SearchToolsQuery) -> Self {
+    Self {
+      name: params.name_weight.unwrap_or(NAME_VECTOR_WEIGHT),
+      description: params.description_weight.unwrap_or(DESCRIPTION_VECTOR_WEIGHT),
+      schema: params.schema_weight.unwrap_or(SCHEMA_VECTOR_WEIGHT),
+    }
+  }
 }
 
+#[instrument(skip_all, fields(batch_id = %params.batch_id, query = ?params.query))]
 pub(crate) async fn search_tools(State(state): State<AppState>, Query(params): Query<SearchToolsQuery>) -> impl IntoResponse {
+  if let Err(error) = params.validate() {
+    return (StatusCode::BAD_REQUEST, error).into_response();
+  }
+
+  touch_batch(&state, &params.batch_id).await;
+
   let app_data = state.read().await;
-  let qdrant = &app_data.qdrant;
+  let vector_store = &app_data.vector_store;
   let pool = &app_data.pool;
+  let weights = VectorWeights::from_query(&params);
+  let query = params.query.as_deref().filter(|q| !q.is_empty());
+  let mode = params.routing.unwrap_or_default();
+
+  let features = match params.features.as_deref().map(serde_json::from_str::<HashMap<String, String>>) {
+    Some(Ok(features)) if features.len() > MAX_TOOL_CALL_FEATURES => {
+      return (
+        StatusCode::BAD_REQUEST,
+        format!("at most {} features are supported", MAX_TOOL_CALL_FEATURES),
+      )
+        .into_response();
+    }
+    Some(Ok(features)) => Some(features),
+    Some(Err(e)) => return (StatusCode::BAD_REQUEST, format!("invalid features: {}", e)).into_response(),
+    None => None,
+  };
 
-  println!("Searching for tools...");
+  record_search();
 
-  let Some(urls) = app_data.batch_map.get(&params.batch_id) else {
-    eprintln!("No active registration with id {}", params.batch_id);
+  let Some(batch) = app_data.batch_map.get(&params.batch_id) else {
+    warn!("No active registration with this id");
     return (StatusCode::NOT_FOUND, Json(Vec::<ToolResult>::new())).into_response();
   };
+  let urls = &batch.urls;
 
-  let mut points = Vec::new();
-  let mut next_page = None;
-
-  loop {
-    let scroll_response = qdrant
-      .scroll(ScrollPoints {
-        collection_name: QDRANT_COLLECTION_NAME.to_string(),
-        filter: Some(Filter::must([Condition::matches(
-          "mcp_url",
-          urls.iter().cloned().collect::<Vec<_>>(),
-        )])),
-        with_payload: Some(true.into()),
-        with_vectors: Some(true.into()),
-        limit: Some(100),
-        offset: next_page,
-        ..Default::default()
-      })
-      .await;
+  let reachable_urls = urls.iter().filter(|url| {
+    let is_down = app_data
+      .servers
+      .get(*url)
+      .is_some_and(|status| status.health.state() == HealthState::Down);
+    if is_down {
+      info!(mcp_url = %url, "Skipping tools of a server that is down");
+    }
+    !is_down
+  });
+  let filter = PayloadFilter::matches("mcp_url", reachable_urls.cloned());
 
-    match scroll_response {
-      Ok(resp) => {
-        points.extend(resp.result);
-        next_page = resp.next_page_offset;
-        if next_page.is_none() {
-          break;
-        }
+  let mut points = match vector_store.scroll(TOOL_COLLECTION_NAME, Some(&filter), true).await {
+    Ok(points) => points,
+    Err(e) => {
+      error!(error = %e, "Failed to scroll vector store");
+      return (StatusCode::INTERNAL_SERVER_ERROR, Json(Vec::<ToolResult>::new())).into_response();
+    }
+  };
+
+  let mut query_scores: HashMap<String, f32> = HashMap::new();
+
+  // With no reachable tools there is nothing to rank, and a similarity query would ask for 0 hits.
+  if !points.is_empty()
+    && let Some(query) = query
+  {
+    let query_vector = match generate_embedding_with_model(query, &app_data.embedding_model).await {
+      Ok(vector) => vector,
+      Err(e) => {
+        error!(error = %e, "Failed to embed search query");
+        return (StatusCode::BAD_GATEWAY, Json(Vec::<ToolResult>::new())).into_response();
       }
+    };
+
+    query_scores = match query_similarity(vector_store.as_ref(), &query_vector, &filter, points.len(), weights).await {
+      Ok(scores) => scores,
       Err(e) => {
-        eprintln!("Failed to scroll Qdrant: {}", e);
+        error!(error = %e, "Failed to search vector store");
         return (StatusCode::INTERNAL_SERVER_ERROR, Json(Vec::<ToolResult>::new())).into_response();
       }
-    }
+    };
+
+    let threshold = params.score_threshold.unwrap_or(DEFAULT_MIN_SIMILARITY);
+    query_scores.retain(|_, score| *score >= threshold);
+    points.retain(|point| query_scores.contains_key(&point.id));
   }
 
   if points.is_empty() {
     return (StatusCode::OK, Json(Vec::<ToolResult>::new())).into_response();
   }
 
-  let all_clustered_tools = cluster_data(points.clone());
+  let similar_pairs = match vector_store
+    .similar_pairs(TOOL_COLLECTION_NAME, &points, weights, CLUSTER_SIMILARITY_THRESHOLD)
+    .await
+  {
+    Ok(pairs) => pairs,
+    Err(e) => {
+      error!(error = %e, "Failed to compare tools");
+      return (StatusCode::INTERNAL_SERVER_ERROR, Json(Vec::<ToolResult>::new())).into_response();
+    }
+  };
+
+  let all_clustered_tools = cluster_data(points, &similar_pairs);
+  let cluster_count = all_clustered_tools.len();
+
+  let (prices, remaining_budget) = if mode.is_budget_aware() {
+    let mcp_urls: Vec<String> = urls.iter().cloned().collect();
+    let budget = async {
+      anyhow::Ok((
+        load_prices(pool, &mcp_urls).await?,
+        remaining_budget(pool, &params.batch_id, batch.budget).await?,
+      ))
+    };
+    match budget.await {
+      Ok((prices, remaining_budget)) => (Some(prices), remaining_budget),
+      Err(e) => {
+        error!(error = %e, "Failed to load prices or budget");
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(Vec::<ToolResult>::new())).into_response();
+      }
+    }
+  } else {
+    (None, None)
+  };
 
-  let fastest_tools: Vec<_> = join_all(all_clustered_tools.into_iter().map(|original_tool_category| {
+  let rtt_increases: HashMap<String, f64> = urls
+    .iter()
+    .filter_map(|url| {
+      app_data
+        .servers
+        .get(url)
+        .and_then(rtt_increase_ms)
+        .map(|increase| (url.clone(), increase))
+    })
+    .collect();
+  let rtt_weight = params.rtt_weight.unwrap_or(DEFAULT_RTT_WEIGHT);
+  let output_token_weight = params.output_token_weight.unwrap_or(DEFAULT_OUTPUT_TOKEN_WEIGHT);
+  let cost_inputs = &CostInputs {
+    rtt_increases: &rtt_increases,
+    rtt_weight,
+    output_token_weight,
+    features: features.as_ref().filter(|_| mode == RoutingMode::InputAware),
+    prices: prices.as_ref(),
+    deadline_ms: params.deadline_ms,
+  };
+  let latency_target_ms = params.latency_target_ms;
+
+  let query_scores = &query_scores;
+  let outcomes: Vec<(RoutingDecision, Option<ToolResult>)> = join_all(all_clustered_tools.into_iter().map(|tool_category| {
     let pool = pool.clone();
     async move {
-      let mut errored_tools_in_category: Vec<(String, String)> = Vec::new();
+      let mut circuit_open = Vec::with_capacity(tool_category.len());
 
-      for point in &original_tool_category {
-        let tool_name = extract_string_from_payload(&point.payload, "name").unwrap_or_default();
-        let mcp_url = extract_string_from_payload(&point.payload, "mcp_url").unwrap_or_default();
+      for point in &tool_category {
+        let tool_name = point.payload_str("name").unwrap_or_default();
+        let mcp_url = point.payload_str("mcp_url").unwrap_or_default();
 
+        // Only errors agents ran into open the circuit; probe failures already show in latencies.
         let error_count = sqlx::query_scalar!(
           r#"
           SELECT COUNT(*) AS "count!"
@@ -87,6 +300,8 @@ pub(crate) async fn search_tools(State(state): State<AppState>, Query(params): Q
             AND mcp_url = $2
             AND is_error = TRUE
             AND timestamp > NOW() - INTERVAL '1 minute' * $3
+            AND NOT unknown_tool AND NOT unknown_batch
+            AND source <> 'probe'
           "#,
           tool_name,
           mcp_url,
@@ -96,98 +311,460 @@ pub(crate) async fn search_tools(State(state): State<AppState>, Query(params): Q
         .await
         .unwrap_or(0);
 
-        if error_count >= N_ERROR_THRESHOLD {
-          errored_tools_in_category.push((tool_name, mcp_url));
-        }
+        circuit_open.push(error_count >= N_ERROR_THRESHOLD);
       }
 
-      let filtered_tool_category: Vec<RetrievedPoint> = original_tool_category
-        .clone()
-        .into_iter()
-        .filter(|point| {
-          let tool_name = extract_string_from_payload(&point.payload, "name").unwrap_or_default();
-          let mcp_url = extract_string_from_payload(&point.payload, "mcp_url").unwrap_or_default();
-          !errored_tools_in_category.contains(&(tool_name, mcp_url))
-        })
-        .collect();
+      let all_circuits_open = circuit_open.iter().all(|open| *open);
+      let ranked = |i: usize| all_circuits_open || !circuit_open[i];
 
-      let mut candidates = join_all(filtered_tool_category.into_iter().map(|tool| {
-        let pool = pool.clone();
+      let costs: Vec<Option<RoutingCost>> = join_all(tool_category.iter().enumerate().map(|(i, tool)| {
+        let pool = &pool;
         async move {
-          let avg_time = sqlx::query_scalar!(
-            r#"
-            SELECT AVG(total_time_ms)::FLOAT AS "avg_total_time_ms!"
-            FROM (
-                SELECT total_time_ms
-                FROM tool_call_results
-                WHERE tool_name = $1 AND mcp_url = $2 AND is_error = FALSE
-                ORDER BY timestamp DESC
-                LIMIT $3
-            ) AS recent_logs
-            "#,
-            extract_string_from_payload(&tool.payload, "name").unwrap_or_default(),
-            extract_string_from_payload(&tool.payload, "mcp_url").unwrap_or_default(),
-            MAX_TOOL_CALL_LOGS
-          )
-          .fetch_one(&pool)
-          .await
-          .unwrap_or(0.0);
-
-          (tool, avg_time)
+          if ranked(i) {
+            Some(routing_cost(pool, tool, cost_inputs).await)
+          } else {
+            None
+          }
         }
       }))
-      .await
-      .into_iter()
-      .min_by(|(_, a), (_, b)| a.total_cmp(b));
-
-      if candidates.is_none() && !original_tool_category.is_empty() {
-        candidates = join_all(original_tool_category.into_iter().map(|tool| {
-          let pool = pool.clone();
-          async move {
-            let avg_time = sqlx::query_scalar!(
-              r#"
-              SELECT AVG(total_time_ms)::FLOAT AS "avg_total_time_ms!"
-              FROM (
-                  SELECT total_time_ms
-                  FROM tool_call_results
-                  WHERE tool_name = $1 AND mcp_url = $2 AND is_error = FALSE
-                  ORDER BY timestamp DESC
-                  LIMIT $3
-              ) AS recent_logs
-              "#,
-              extract_string_from_payload(&tool.payload, "name").unwrap_or_default(),
-              extract_string_from_payload(&tool.payload, "mcp_url").unwrap_or_default(),
-              MAX_TOOL_CALL_LOGS
-            )
-            .fetch_one(&pool)
-            .await
-            .unwrap_or(0.0);
-
-            (tool, avg_time)
-          }
-        }))
-        .await
-        .into_iter()
-        .min_by(|(_, a), (_, b)| a.total_cmp(b));
-      }
+      .await;
+
+      let selection = select_candidate(mode, &costs, remaining_budget, latency_target_ms, params.deadline_ms);
+
+      let candidates: Vec<RoutingCandidate> = tool_category
+        .iter()
+        .zip(costs)
+        .zip(circuit_open)
+        .map(|((tool, cost), circuit_open)| RoutingCandidate {
+          name: tool.payload_str("name").unwrap_or_default(),
+          mcp_url: tool.payload_str("mcp_url").unwrap_or_default(),
+          score: query_scores.get(&tool.id).copied(),
+          cost_ms: cost.as_ref().map(|cost| cost.cost_ms),
+          price: cost.as_ref().and_then(|cost| cost.price),
+          on_time_probability: cost.as_ref().and_then(|cost| cost.on_time_probability),
+          p95_latency_ms: cost.and_then(|cost| cost.p95_latency_ms),
+          circuit_open,
+        })
+        .collect();
+
+      let Some((selected, strategy)) = selection else {
+        info!(tool_count = tool_category.len(), "No tool of the cluster fits the remaining budget");
+        let decision = RoutingDecision {
+          strategy: RoutingStrategy::OverBudget,
+          selected_tool_name: None,
+          selected_mcp_url: None,
+          candidates,
+        };
+        return (decision, None);
+      };
+      let strategy = if all_circuits_open {
+        RoutingStrategy::CircuitOpenFallback
+      } else {
+        strategy
+      };
 
-      candidates.map(|(tool, _)| tool)
+      let tool = &tool_category[selected];
+      let result = ToolResult {
+        mcp_url: tool.payload_str("mcp_url").unwrap_or_default(),
+        name: tool.payload_str("name").unwrap_or_default(),
+        score: query_scores.get(&tool.id).copied(),
+      };
+
+      (
+        RoutingDecision {
+          strategy,
+          selected_tool_name: Some(result.name.clone()),
+          selected_mcp_url: Some(result.mcp_url.clone()),
+          candidates,
+        },
+        Some(result),
+      )
     }
   }))
-  .await
-  .into_iter()
-  .flatten()
-  .map(|node| ToolResult {
-    mcp_url: extract_string_from_payload(&node.payload, "mcp_url").unwrap_or_default(),
-    name: extract_string_from_payload(&node.payload, "name").unwrap_or_default(),
-  })
-  .collect();
+  .await;
+
+  let mut decisions = Vec::with_capacity(outcomes.len());
+  let mut over_budget = Vec::new();
+  for (decision, result) in outcomes {
+    match result {
+      Some(result) => decisions.push((decision, result)),
+      None => over_budget.push(decision),
+    }
+  }
+
+  let request = RoutingRequest {
+    batch_id: params.batch_id.clone(),
+    query: query.map(str::to_string),
+    mode,
+    rtt_weight,
+    output_token_weight,
+    features,
+    latency_target_ms,
+    remaining_budget,
+    deadline_ms: params.deadline_ms,
+  };
+
+  if decisions.is_empty() && cluster_count > 0 {
+    warn!(remaining_budget, "Remaining budget cannot afford any tool");
+    tokio::spawn(record_decisions(pool.clone(), request, over_budget));
+    return (StatusCode::PAYMENT_REQUIRED, Json(Vec::<ToolResult>::new())).into_response();
+  }
+
+  if query.is_some() {
+    decisions.sort_by(|(_, a), (_, b)| b.score.unwrap_or_default().total_cmp(&a.score.unwrap_or_default()));
+    decisions.truncate(params.limit.unwrap_or(DEFAULT_TOOL_LIMIT));
+  }
+
+  let (mut decisions, fastest_tools): (Vec<_>, Vec<_>) = decisions.into_iter().unzip();
+  decisions.extend(over_budget);
 
-  println!("Found tools: {:#?}", fastest_tools);
+  tokio::spawn(record_decisions(pool.clone(), request, decisions));
+
+  for tool in &fastest_tools {
+    record_selection(&tool.name, &tool.mcp_url);
+  }
+
+  info!(tool_count = fastest_tools.len(), "Found tools");
+  debug!(tools = ?fastest_tools);
 
   (StatusCode::OK, Json(Some(fastest_tools))).into_response()
 }
 
+/// Expected latency of calling `tool`, plus `rtt_weight` times how much the round trip to its
+/// server rose over its usual one, so a server that suddenly becomes slow to reach loses out
+/// before agents log slow calls, plus `output_token_weight` times its expected output in tokens. The latency is
+/// predicted from the intended call's features when routing is input-aware and there is
+/// enough history for them, and is the tool's recent mean otherwise. Tools without logged
+/// output sizes are not charged for their output. Priced tools are also given the price of
+/// such a call when routing is budget-aware, and every tool its fit to the deadline if there
+/// is one.
+async fn routing_cost(pool: &PgPool, tool: &ToolPoint, inputs: &CostInputs<'_>) -> RoutingCost {
+  let tool_name = tool.payload_str("name").unwrap_or_default();
+  let mcp_url = tool.payload_str("mcp_url").unwrap_or_default();
+
+  let predicted = match inputs.features {
+    Some(features) => feature_latency_ms(pool, &tool_name, &mcp_url, features).await,
+    None => None,
+  };
+  let avg_time = match predicted {
+    Some(predicted) => predicted,
+    None => recent_latency_ms(pool, &tool_name, &mcp_url).await,
+  };
+
+  let rtt_increase = inputs.rtt_increases.get(&mcp_url).copied().unwrap_or_default();
+
+  let price = inputs.prices.and_then(|prices| prices.get(&mcp_url, &tool_name));
+  let output_tokens = if inputs.output_token_weight > 0.0 || price.is_some_and(|price| price.unit == Some(PriceUnit::OutputToken)) {
+    expected_output_tokens(pool, &tool_name, &mcp_url).await
+  } else {
+    0.0
+  };
+
+  let fit = match inputs.deadline_ms {
+    Some(deadline_ms) => deadline_fit(pool, &tool_name, &mcp_url, deadline_ms - inputs.rtt_weight * rtt_increase).await,
+    None => DeadlineFit::default(),
+  };
+
+  RoutingCost {
+    cost_ms: avg_time + inputs.rtt_weight * rtt_increase + inputs.output_token_weight * output_tokens,
+    latency_ms: avg_time + inputs.rtt_weight * rtt_increase,
+    price: price.map(|price| price.of_call(avg_time, Some(output_tokens))),
+    on_time_probability: fit.on_time_probability,
+    p95_latency_ms: fit.p95_latency_ms.map(|p95| p95 + inputs.rtt_weight * rtt_increase),
+  }
+}
+
+/// Estimates from the tool's last `DEADLINE_SAMPLES` calls how likely a call is to succeed
+/// within `deadline_ms`; failed calls count as late. Once those fell out of the raw retention
+/// the latest rollup is used instead, whose percentiles only give a lower bound.
+async fn deadline_fit(pool: &PgPool, tool_name: &str, mcp_url: &str, deadline_ms: f64) -> DeadlineFit {
+  let recent = sqlx::query!(
+    r#"
+    SELECT
+      COUNT(*) AS "call_count!",
+      COUNT(*) FILTER (WHERE NOT is_error AND total_time_ms <= $3::FLOAT8) AS "on_time_count!",
+      PERCENTILE_CONT(0.95) WITHIN GROUP (ORDER BY total_time_ms) FILTER (WHERE NOT is_error) AS p95_latency_ms
+    FROM (
+        SELECT total_time_ms, is_error
+        FROM tool_call_results
+        WHERE tool_name = $1 AND mcp_url = $2 AND NOT unknown_tool AND NOT unknown_batch
+        ORDER BY timestamp DESC
+        LIMIT $4
+    ) AS recent_calls
+    "#,
+    tool_name,
+    mcp_url,
+    deadline_ms,
+    DEADLINE_SAMPLES
+  )
+  .fetch_one(pool)
+  .await;
+
+  match recent {
+    Ok(recent) if recent.call_count > 0 => {
+      return DeadlineFit {
+        on_time_probability: Some(recent.on_time_count as f64 / recent.call_count as f64),
+        p95_latency_ms: recent.p95_latency_ms,
+      };
+    }
+    Ok(_) => {}
+    Err(e) => {
+      warn!(tool_name, mcp_url, error = %e, "Failed to load recent latencies");
+      return DeadlineFit::default();
+    }
+  }
+
+  let rollup = sqlx::query!(
+    r#"
+    SELECT call_count, success_count, p50_latency_ms, p95_latency_ms, p99_latency_ms
+    FROM tool_call_rollups
+    WHERE tool_name = $1 AND mcp_url = $2 AND call_count > 0
+    ORDER BY bucket_start DESC, bucket_seconds
+    LIMIT 1
+    "#,
+    tool_name,
+    mcp_url
+  )
+  .fetch_optional(pool)
+  .await;
+
+  match rollup {
+    Ok(Some(rollup)) => {
+      let on_time_share = percentile_on_time_share(deadline_ms, rollup.p50_latency_ms, rollup.p95_latency_ms, rollup.p99_latency_ms);
+      DeadlineFit {
+        on_time_probability: Some(on_time_share * rollup.success_count as f64 / rollup.call_count as f64),
+        p95_latency_ms: rollup.p95_latency_ms,
+      }
+    }
+    Ok(None) => DeadlineFit::default(),
+    Err(e) => {
+      warn!(tool_name, mcp_url, error = %e, "Failed to load latency rollup");
+      DeadlineFit::default()
+    }
+  }
+}
+
+/// Share of successful calls known to finish within `deadline_ms` from a rollup's percentiles:
+/// the highest percentile meeting it, or none.
+fn percentile_on_time_share(deadline_ms: f64, p50: Option<f64>, p95: Option<f64>, p99: Option<f64>) -> f64 {
+  let meets = |percentile: Option<f64>| percentile.is_some_and(|latency| latency <= deadline_ms);
+  if meets(p99) {
+    0.99
+  } else if meets(p95) {
+    0.95
+  } else if meets(p50) {
+    0.5
+  } else {
+    0.0
+  }
+}
+
+/// Picks the member of a cluster to route to among the ranked ones, i.e. those with a
+/// cost, leaving out the ones whose expected price exceeds `remaining_budget` and, given a
+/// deadline, the ones whose p95 latency exceeds it. Free tools stay affordable once the
+/// budget is used up, and tools without latency history are kept. If no tool meets the
+/// deadline the affordable tool most likely to finish in time is picked anyway. Returns
+/// `None` if no ranked tool is affordable.
+fn select_candidate(
+  mode: RoutingMode,
+  costs: &[Option<RoutingCost>],
+  remaining_budget: Option<f64>,
+  latency_target_ms: Option<f64>,
+  deadline_ms: Option<f64>,
+) -> Option<(usize, RoutingStrategy)> {
+  let affordable: Vec<(usize, &RoutingCost)> = costs
+    .iter()
+    .enumerate()
+    .filter_map(|(i, cost)| cost.as_ref().map(|cost| (i, cost)))
+    .filter(|(_, cost)| remaining_budget.is_none_or(|remaining| cost.price.is_none_or(|price| price <= remaining.max(0.0))))
+    .collect();
+
+  let fastest = |candidates: &[(usize, &RoutingCost)]| {
+    candidates
+      .iter()
+      .min_by(|(_, a), (_, b)| a.cost_ms.total_cmp(&b.cost_ms))
+      .map(|(i, _)| *i)
+  };
+  let most_likely_on_time = |candidates: &[(usize, &RoutingCost)]| {
+    candidates
+      .iter()
+      .max_by(|(_, a), (_, b)| {
+        let (a_probability, b_probability) = (a.on_time_probability.unwrap_or_default(), b.on_time_probability.unwrap_or_default());
+        a_probability.total_cmp(&b_probability).then(b.cost_ms.total_cmp(&a.cost_ms))
+      })
+      .map(|(i, _)| *i)
+  };
+
+  let candidates = match deadline_ms {
+    Some(deadline_ms) => {
+      let meets_deadline: Vec<(usize, &RoutingCost)> = affordable
+        .iter()
+        .filter(|(_, cost)| cost.p95_latency_ms.is_none_or(|p95| p95 <= deadline_ms))
+        .copied()
+        .collect();
+      if meets_deadline.is_empty() {
+        return Some((most_likely_on_time(&affordable)?, RoutingStrategy::DeadlineUnreachable));
+      }
+      meets_deadline
+    }
+    None => affordable,
+  };
+
+  match mode {
+    RoutingMode::Cheapest => {
+      let on_target: Vec<(usize, &RoutingCost)> = candidates
+        .iter()
+        .filter(|(_, cost)| latency_target_ms.is_none_or(|target| cost.latency_ms <= target))
+        .copied()
+        .collect();
+
+      let cheapest = on_target
+        .iter()
+        .min_by(|(_, a), (_, b)| {
+          let (a_price, b_price) = (a.price.unwrap_or_default(), b.price.unwrap_or_default());
+          a_price.total_cmp(&b_price).then(a.cost_ms.total_cmp(&b.cost_ms))
+        })
+        .map(|(i, _)| *i);
+
+      match cheapest {
+        Some(i) => Some((i, RoutingStrategy::Cheapest)),
+        None => Some((fastest(&candidates)?, RoutingStrategy::LatencyTargetMissed)),
+      }
+    }
+    _ if deadline_ms.is_some() => Some((most_likely_on_time(&candidates)?, RoutingStrategy::MostLikelyOnTime)),
+    RoutingMode::Latency | RoutingMode::InputAware => Some((fastest(&candidates)?, RoutingStrategy::LowestCost)),
+    RoutingMode::FastestWithinBudget => Some((fastest(&candidates)?, RoutingStrategy::WithinBudget)),
+  }
+}
+
+/// Mean output, in tokens, of the tool's last `OUTPUT_SIZE_SAMPLES` successful calls that
+/// reported it, or of its latest rollup with output sizes once those fell out of the raw
+/// retention. Sizes in bytes are converted at `BYTES_PER_TOKEN`.
+async fn expected_output_tokens(pool: &PgPool, tool_name: &str, mcp_url: &str) -> f64 {
+  sqlx::query_scalar!(
+    r#"
+    SELECT COALESCE(
+      (
+        SELECT AVG(COALESCE(response_tokens::FLOAT8, response_bytes / $3::FLOAT8))
+        FROM (
+            SELECT response_tokens, response_bytes
+            FROM tool_call_results
+            WHERE tool_name = $1
+              AND mcp_url = $2
+              AND is_error = FALSE
+              AND (response_tokens IS NOT NULL OR response_bytes IS NOT NULL)
+              AND NOT unknown_tool AND NOT unknown_batch
+            ORDER BY timestamp DESC
+            LIMIT $4
+        ) AS recent_outputs
+      ),
+      (
+        SELECT COALESCE(
+          response_tokens_sum / NULLIF(response_tokens_count, 0),
+          response_bytes_sum / NULLIF(response_bytes_count, 0) / $3
+        )
+        FROM tool_call_rollups
+        WHERE tool_name = $1 AND mcp_url = $2 AND (response_tokens_count > 0 OR response_bytes_count > 0)
+        ORDER BY bucket_start DESC, bucket_seconds
+        LIMIT 1
+      ),
+      0
+    ) AS "expected_tokens!"
+    "#,
+    tool_name,
+    mcp_url,
+    BYTES_PER_TOKEN,
+    OUTPUT_SIZE_SAMPLES
+  )
+  .fetch_one(pool)
+  .await
+  .unwrap_or(0.0)
+}
+
+/// Mean of the tool's last `MAX_TOOL_CALL_LOGS` successful calls, or of its latest rollup
+/// once those fell out of the raw retention.
+async fn recent_latency_ms(pool: &PgPool, tool_name: &str, mcp_url: &str) -> f64 {
+  sqlx::query_scalar!(
+    r#"
+    SELECT COALESCE(
+      (
+        SELECT AVG(total_time_ms)::FLOAT8
+        FROM (
+            SELECT total_time_ms
+            FROM tool_call_results
+            WHERE tool_name = $1 AND mcp_url = $2 AND is_error = FALSE AND NOT unknown_tool AND NOT unknown_batch
+            ORDER BY timestamp DESC
+            LIMIT $3
+        ) AS recent_logs
+      ),
+      (
+        SELECT latency_sum_ms / success_count
+        FROM tool_call_rollups
+        WHERE tool_name = $1 AND mcp_url = $2 AND success_count > 0
+        ORDER BY bucket_start DESC, bucket_seconds
+        LIMIT 1
+      )
+    ) AS "avg_total_time_ms!"
+    "#,
+    tool_name,
+    mcp_url,
+    MAX_TOOL_CALL_LOGS
+  )
+  .fetch_one(pool)
+  .await
+  .unwrap_or(0.0)
+}
+
+/// Predicts the latency of calling the tool with `features` from the last
+/// `FEATURE_LATENCY_SAMPLES` successful calls sharing each feature: the means per feature,
+/// weighted by their number of calls. Features seen in fewer than `MIN_FEATURE_SAMPLES`
+/// calls are ignored, and `None` is returned if that leaves none.
+async fn feature_latency_ms(pool: &PgPool, tool_name: &str, mcp_url: &str, features: &HashMap<String, String>) -> Option<f64> {
+  let (keys, values): (Vec<String>, Vec<String>) = features.iter().map(|(k, v)| (k.clone(), v.clone())).unzip();
+
+  let rows = sqlx::query!(
+    r#"
+    SELECT COUNT(recent.total_time_ms) AS "sample_count!", AVG(recent.total_time_ms)::FLOAT8 AS mean_ms
+    FROM UNNEST($3::TEXT[], $4::TEXT[]) AS requested(key, value)
+    LEFT JOIN LATERAL (
+      SELECT total_time_ms
+      FROM tool_call_results
+      WHERE tool_name = $1
+        AND mcp_url = $2
+        AND is_error = FALSE
+        AND features @> JSONB_BUILD_OBJECT(requested.key, requested.value)
+        AND NOT unknown_tool AND NOT unknown_batch
+      ORDER BY timestamp DESC
+      LIMIT $5
+    ) AS recent ON TRUE
+    GROUP BY requested.key
+    "#,
+    tool_name,
+    mcp_url,
+    &keys,
+    &values,
+    FEATURE_LATENCY_SAMPLES
+  )
+  .fetch_all(pool)
+  .await;
+
+  let rows = match rows {
+    Ok(rows) => rows,
+    Err(e) => {
+      warn!(tool_name, mcp_url, error = %e, "Failed to load latency per feature");
+      return None;
+    }
+  };
+
+  let (latency_sum, sample_count) = rows
+    .iter()
+    .filter(|row| row.sample_count >= MIN_FEATURE_SAMPLES)
+    .filter_map(|row| row.mean_ms.map(|mean| (mean * row.sample_count as f64, row.sample_count)))
+    .fold((0.0, 0), |(sum, count), (latency, samples)| (sum + latency, count + samples));
+
+  (sample_count > 0).then(|| latency_sum / sample_count as f64)
+}
+
 /// Finds clusters of tools based on the definition embeddings
 /// i.e.:
 /// tool 1 description: scrape a website
@@ -196,106 +773,263 @@ pub(crate) async fn search_tools(State(state): State<AppState>, Query(params): Q
 /// tool 3 description: Find the sum of two numbers
 ///
 /// Expected return value: vec![vec![tool1, tool2], vec![tool3]]
-fn cluster_data(points: Vec<RetrievedPoint>) -> Vec<Vec<RetrievedPoint>> {
-  let mut clusters: Vec<Vec<RetrievedPoint>> = Vec::new();
+///
+/// Two tools are similar when `similar_pairs` contains their ids, as computed by
+/// `VectorStore::similar_pairs` from the weighted name, description and schema vectors.
+fn cluster_data(points: Vec<ToolPoint>, similar_pairs: &HashSet<(String, String)>) -> Vec<Vec<ToolPoint>> {
+  let mut clusters: Vec<Vec<ToolPoint>> = Vec::new();
 
   for point in points {
-    let point_vec_opt = get_vector(&point.vectors);
-
-    if let Some(point_vec) = point_vec_opt {
-      let mut assigned = false;
-      for cluster in &mut clusters {
-        let is_similar = cluster.iter().any(|member| {
-          if let Some(member_vec) = get_vector(&member.vectors) {
-            cosine_similarity(point_vec, member_vec) >= CLUSTER_SIMILARITY_THRESHOLD
-          } else {
-            false
-          }
-        });
+    if point.vectors.is_empty() {
+      warn!(point_id = %point.id, "Failed to get vectors for point");
+      clusters.push(vec![point]);
+      continue;
+    }
 
-        if is_similar {
-          cluster.push(point.clone());
-          assigned = true;
-          break;
-        }
-      }
+    let similar_cluster = clusters.iter_mut().find(|cluster| {
+      cluster
+        .iter()
+        .any(|member| similar_pairs.contains(&(point.id.clone(), member.id.clone())))
+    });
 
-      if !assigned {
-        clusters.push(vec![point]);
-      }
-    } else {
-      eprintln!("Failed to get vector for point: {:?}", point.id);
-      clusters.push(vec![point]);
+    match similar_cluster {
+      Some(cluster) => cluster.push(point),
+      None => clusters.push(vec![point]),
     }
   }
 
   clusters
 }
 
-fn get_vector(vectors: &Option<VectorsOutput>) -> Option<&Vec<f32>> {
-  match vectors {
-    Some(vectors) => match &vectors.vectors_options {
-      Some(qdrant_client::qdrant::vectors_output::VectorsOptions::Vector(v)) => {
-        if !v.data.is_empty() {
-          Some(&v.data)
-        } else {
-          match &v.vector {
-            Some(qdrant_client::qdrant::vector_output::Vector::Dense(d)) => Some(&d.data),
-            _ => {
-              eprintln!("Inner vector is not Dense: {:?}. Full vectors: {:?}", v.vector, vectors);
-              None
-            }
-          }
-        }
-      }
-      Some(qdrant_client::qdrant::vectors_output::VectorsOptions::Vectors(v)) => {
-        if let Some(vector) = v.vectors.values().next() {
-          if !vector.data.is_empty() {
-            Some(&vector.data)
-          } else {
-            match &vector.vector {
-              Some(qdrant_client::qdrant::vector_output::Vector::Dense(d)) => Some(&d.data),
-              _ => {
-                eprintln!("Named vector is not Dense: {:?}", vector);
-                None
-              }
-            }
-          }
-        } else {
-          eprintln!("Named vectors map is empty");
-          None
-        }
-      }
-      None => {
-        eprintln!("VectorsOptions is None");
-        None
-      }
-    },
-    None => {
-      eprintln!("point.vectors is None");
-      None
+/// Scores every tool matching `filter` against a query embedding, as the weighted mean of
+/// the similarities between the query and each of the tool's vectors. Keyed by point id.
+async fn query_similarity(
+  vector_store: &dyn VectorStore,
+  query: &[f32],
+  filter: &PayloadFilter,
+  limit: usize,
+  weights: VectorWeights,
+) -> anyhow::Result<HashMap<String, f32>> {
+  let mut totals: HashMap<String, (f32, f32)> = HashMap::new();
+
+  for (name, weight) in weights.iter() {
+    if weight <= 0.0 {
+      continue;
+    }
+    for (point, score) in vector_store.search(TOOL_COLLECTION_NAME, name, query, Some(filter), limit).await? {
+      let (total, weight_sum) = totals.entry(point.id).or_default();
+      *total += weight * score;
+      *weight_sum += weight;
     }
   }
+
+  Ok(
+    totals
+      .into_iter()
+      .map(|(id, (total, weight_sum))| (id, if weight_sum == 0.0 { 0.0 } else { total / weight_sum }))
+      .collect(),
+  )
 }
 
-fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
-  let dot_product: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
-  let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
-  let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+#[cfg(test)]
+mod tests {
+  use super::*;
 
-  if norm_a == 0.0 || norm_b == 0.0 {
-    0.0
-  } else {
-    dot_product / (norm_a * norm_b)
+  fn cost(cost_ms: f64, price: Option<f64>, p95_latency_ms: Option<f64>, on_time_probability: Option<f64>) -> Option<RoutingCost> {
+    Some(RoutingCost {
+      cost_ms,
+      latency_ms: cost_ms,
+      price,
+      on_time_probability,
+      p95_latency_ms,
+    })
   }
-}
 
-fn extract_string_from_payload(payload: &std::collections::HashMap<String, qdrant_client::qdrant::Value>, key: &str) -> Option<String> {
-  payload.get(key).and_then(|value| {
-    if let Some(qdrant_client::qdrant::value::Kind::StringValue(s)) = &value.kind {
-      Some(s.clone())
-    } else {
-      None
+  #[test]
+  fn select_candidate_cases() {
+    use RoutingMode as M;
+    use RoutingStrategy as S;
+
+    let cases = [
+      (
+        "lowest cost wins",
+        M::Latency,
+        vec![
+          cost(300.0, None, None, None),
+          cost(100.0, None, None, None),
+          cost(200.0, None, None, None),
+        ],
+        None,
+        None,
+        None,
+        Some((1, S::LowestCost)),
+      ),
+      (
+        "unranked members are skipped",
+        M::Latency,
+        vec![None, cost(200.0, None, None, None)],
+        None,
+        None,
+        None,
+        Some((1, S::LowestCost)),
+      ),
+      ("nothing ranked", M::Latency, vec![None, None], None, None, None, None),
+      (
+        "budget leaves out pricier tools",
+        M::FastestWithinBudget,
+        vec![cost(100.0, Some(5.0), None, None), cost(300.0, Some(0.5), None, None)],
+        Some(1.0),
+        None,
+        None,
+        Some((1, S::WithinBudget)),
+      ),
+      (
+        "free tools stay affordable once the budget is spent",
+        M::FastestWithinBudget,
+        vec![cost(100.0, Some(1.0), None, None), cost(300.0, None, None, None)],
+        Some(-2.0),
+        None,
+        None,
+        Some((1, S::WithinBudget)),
+      ),
+      (
+        "nothing affordable",
+        M::FastestWithinBudget,
+        vec![cost(100.0, Some(5.0), None, None)],
+        Some(1.0),
+        None,
+        None,
+        None,
+      ),
+      (
+        "cheapest without a target",
+        M::Cheapest,
+        vec![cost(100.0, Some(1.0), None, None), cost(500.0, Some(0.1), None, None)],
+        None,
+        None,
+        None,
+        Some((1, S::Cheapest)),
+      ),
+      (
+        "cheapest within the latency target",
+        M::Cheapest,
+        vec![cost(100.0, Some(1.0), None, None), cost(500.0, Some(0.1), None, None)],
+        None,
+        Some(200.0),
+        None,
+        Some((0, S::Cheapest)),
+      ),
+      (
+        "fastest once no tool meets the latency target",
+        M::Cheapest,
+        vec![cost(400.0, Some(1.0), None, None), cost(500.0, Some(0.1), None, None)],
+        None,
+        Some(200.0),
+        None,
+        Some((0, S::LatencyTargetMissed)),
+      ),
+      (
+        "deadline prefers the tool most likely on time",
+        M::Latency,
+        vec![
+          cost(100.0, None, Some(250.0), Some(0.8)),
+          cost(150.0, None, Some(280.0), Some(0.97)),
+        ],
+        None,
+        None,
+        Some(300.0),
+        Some((1, S::MostLikelyOnTime)),
+      ),
+      (
+        "deadline leaves out tools whose p95 exceeds it",
+        M::Latency,
+        vec![cost(100.0, None, Some(400.0), Some(0.9)), cost(150.0, None, Some(250.0), Some(0.6))],
+        None,
+        None,
+        Some(300.0),
+        Some((1, S::MostLikelyOnTime)),
+      ),
+      (
+        "tools without history are kept under a deadline",
+        M::Latency,
+        vec![cost(100.0, None, Some(400.0), Some(0.9)), cost(150.0, None, None, None)],
+        None,
+        None,
+        Some(300.0),
+        Some((1, S::MostLikelyOnTime)),
+      ),
+      (
+        "unreachable deadline falls back to the tool most likely on time",
+        M::Latency,
+        vec![cost(100.0, None, Some(400.0), Some(0.3)), cost(150.0, None, Some(500.0), Some(0.4))],
+        None,
+        None,
+        Some(300.0),
+        Some((1, S::DeadlineUnreachable)),
+      ),
+      (
+        "cheapest mode only filters by deadline",
+        M::Cheapest,
+        vec![
+          cost(100.0, Some(1.0), Some(250.0), Some(0.9)),
+          cost(150.0, Some(0.5), Some(400.0), Some(0.5)),
+        ],
+        None,
+        None,
+        Some(300.0),
+        Some((0, S::Cheapest)),
+      ),
+    ];
+
+    for (name, mode, costs, remaining_budget, latency_target_ms, deadline_ms, expected) in cases {
+      assert_eq!(
+        select_candidate(mode, &costs, remaining_budget, latency_target_ms, deadline_ms),
+        expected,
+        "{name}"
+      );
+    }
+  }
+
+  #[test]
+  fn percentile_on_time_share_cases() {
+    let cases = [
+      ("p99 meets the deadline", 500.0, Some(100.0), Some(200.0), Some(400.0), 0.99),
+      ("p95 meets the deadline", 300.0, Some(100.0), Some(200.0), Some(400.0), 0.95),
+      ("p50 meets the deadline", 150.0, Some(100.0), Some(200.0), Some(400.0), 0.5),
+      ("no percentile meets the deadline", 50.0, Some(100.0), Some(200.0), Some(400.0), 0.0),
+      ("deadline equal to a percentile", 200.0, Some(100.0), Some(200.0), Some(400.0), 0.95),
+      ("missing percentiles", 500.0, None, None, None, 0.0),
+      ("only p50 known", 500.0, Some(100.0), None, None, 0.5),
+    ];
+
+    for (name, deadline_ms, p50, p95, p99, expected) in cases {
+      assert_eq!(percentile_on_time_share(deadline_ms, p50, p95, p99), expected, "{name}");
     }
-  })
+  }
+
+  #[test]
+  fn validate_search_parameters() {
+    let cases = [
+      ("deadline_ms=300&latency_target_ms=200&rtt_weight=0.5&output_token_weight=0", true),
+      ("rtt_weight=0", true),
+      ("deadline_ms=0", false),
+      ("deadline_ms=-5", false),
+      ("deadline_ms=NaN", false),
+      ("latency_target_ms=inf", false),
+      ("rtt_weight=-1", false),
+      ("output_token_weight=-0.5", false),
+      ("output_token_weight=inf", false),
+      ("name_weight=0&schema_weight=2", true),
+      ("name_weight=-1", false),
+      ("description_weight=NaN", false),
+      ("schema_weight=inf", false),
+      ("name_weight=0&description_weight=0&schema_weight=0", false),
+    ];
+
+    for (query, valid) in cases {
+      let uri = format!("/search?batch_id=b&{query}").parse().unwrap();
+      let Query(params) = Query::<SearchToolsQuery>::try_from_uri(&uri).unwrap();
+      assert_eq!(params.validate().is_ok(), valid, "{query}");
+    }
+  }
 }
@@ -0,0 +1,228 @@
+use std::collections::HashSet;
+
+use axum::{
+  Json,
+  extract::{Query, State},
+  http::StatusCode,
+  response::IntoResponse,
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{error, instrument};
+
+use crate::{
+  M_ERROR_WINDOW_MINUTES, N_ERROR_THRESHOLD,
+  tool_call_rollups::{ToolCallAggregate, merge_aggregates, raw_retained_from, rollup_aggregates},
+  types::AppState,
+};
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+pub(crate) enum StatsWindow {
+  #[serde(rename = "5m")]
+  FiveMinutes,
+  #[serde(rename = "1h")]
+  #[default]
+  Hour,
+  #[serde(rename = "24h")]
+  Day,
+  #[serde(rename = "7d")]
+  Week,
+  #[serde(rename = "30d")]
+  Month,
+  #[serde(rename = "90d")]
+  Quarter,
+}
+
+impl StatsWindow {
+  pub(crate) fn duration(self) -> TimeDelta {
+    match self {
+      StatsWindow::FiveMinutes => TimeDelta::minutes(5),
+      StatsWindow::Hour => TimeDelta::hours(1),
+      StatsWindow::Day => TimeDelta::days(1),
+      StatsWindow::Week => TimeDelta::days(7),
+      StatsWindow::Month => TimeDelta::days(30),
+      StatsWindow::Quarter => TimeDelta::days(90),
+    }
+  }
+}
+
+/// Whether `/search` currently skips the tool: at least `N_ERROR_THRESHOLD` failed calls in
+/// the last `M_ERROR_WINDOW_MINUTES`, regardless of the selected window.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum CircuitState {
+  Closed,
+  Open,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ToolStatsQuery {
+  pub(crate) window: Option<StatsWindow>,
+  /// Restricts the statistics to the servers registered under this batch.
+  pub(crate) batch_id: Option<String>,
+  pub(crate) mcp_url: Option<String>,
+  pub(crate) tool_name: Option<String>,
+}
+
+/// Latencies and output sizes cover successful calls only, like the ones routing is based on.
+/// Calls `/log` flagged as unknown tool or batch are left out, as in routing. Parts of the
+/// window older than the raw retention come from rollups, which makes percentiles approximate.
+#[derive(Serialize)]
+pub(crate) struct ToolStats {
+  pub(crate) tool_name: String,
+  pub(crate) mcp_url: String,
+  pub(crate) call_count: i64,
+  pub(crate) error_count: i64,
+  pub(crate) error_rate: f64,
+  pub(crate) mean_latency_ms: Option<f64>,
+  pub(crate) p50_latency_ms: Option<f64>,
+  pub(crate) p95_latency_ms: Option<f64>,
+  pub(crate) p99_latency_ms: Option<f64>,
+  pub(crate) mean_response_bytes: Option<f64>,
+  pub(crate) mean_response_tokens: Option<f64>,
+  /// What all calls in the window cost, see `/prices`.
+  pub(crate) spend: f64,
+  pub(crate) last_called_at: DateTime<Utc>,
+  pub(crate) circuit_state: CircuitState,
+}
+
+#[derive(Serialize)]
+pub(crate) struct ToolStatsResponse {
+  pub(crate) from: DateTime<Utc>,
+  pub(crate) to: DateTime<Utc>,
+  pub(crate) tools: Vec<ToolStats>,
+}
+
+#[instrument(skip_all, fields(batch_id = ?params.batch_id, mcp_url = ?params.mcp_url, tool_name = ?params.tool_name))]
+pub(crate) async fn get_tool_stats(State(state): State<AppState>, Query(params): Query<ToolStatsQuery>) -> impl IntoResponse {
+  let (pool, batch_urls, retention) = {
+    let app_data = state.read().await;
+    let batch_urls = match &params.batch_id {
+      Some(batch_id) => match app_data.batch_map.get(batch_id) {
+        Some(batch) => Some(batch.urls.iter().cloned().collect::<Vec<_>>()),
+        None => return StatusCode::NOT_FOUND.into_response(),
+      },
+      None => None,
+    };
+    (app_data.pool.clone(), batch_urls, app_data.tool_call_retention)
+  };
+
+  let to = Utc::now();
+  let from = to - params.window.unwrap_or_default().duration();
+  let circuit_from = to - TimeDelta::minutes(M_ERROR_WINDOW_MINUTES);
+  let raw_from = from.max(raw_retained_from(retention));
+  let mcp_urls = match (batch_urls, &params.mcp_url) {
+    (Some(urls), Some(url)) => Some(urls.into_iter().filter(|u| u == url).collect()),
+    (Some(urls), None) => Some(urls),
+    (None, Some(url)) => Some(vec![url.clone()]),
+    (None, None) => None,
+  };
+
+  let rows = sqlx::query!(
+    r#"
+    SELECT
+      tool_name,
+      mcp_url,
+      COUNT(*) FILTER (WHERE timestamp >= $1) AS "call_count!",
+      COUNT(*) FILTER (WHERE timestamp >= $1 AND is_error) AS "error_count!",
+      COUNT(*) FILTER (WHERE timestamp >= $1 AND NOT is_error) AS "success_count!",
+      COALESCE(SUM(total_time_ms) FILTER (WHERE timestamp >= $1 AND NOT is_error), 0)::FLOAT8 AS "latency_sum_ms!",
+      PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY total_time_ms) FILTER (WHERE timestamp >= $1 AND NOT is_error)
+        AS p50_latency_ms,
+      PERCENTILE_CONT(0.95) WITHIN GROUP (ORDER BY total_time_ms) FILTER (WHERE timestamp >= $1 AND NOT is_error)
+        AS p95_latency_ms,
+      PERCENTILE_CONT(0.99) WITHIN GROUP (ORDER BY total_time_ms) FILTER (WHERE timestamp >= $1 AND NOT is_error)
+        AS p99_latency_ms,
+      MAX(timestamp) AS "last_called_at!",
+      COALESCE(SUM(response_bytes) FILTER (WHERE timestamp >= $1 AND NOT is_error), 0)::FLOAT8 AS "response_bytes_sum!",
+      COUNT(response_bytes) FILTER (WHERE timestamp >= $1 AND NOT is_error) AS "response_bytes_count!",
+      COALESCE(SUM(response_tokens) FILTER (WHERE timestamp >= $1 AND NOT is_error), 0)::FLOAT8 AS "response_tokens_sum!",
+      COUNT(response_tokens) FILTER (WHERE timestamp >= $1 AND NOT is_error) AS "response_tokens_count!",
+      COALESCE(SUM(price) FILTER (WHERE timestamp >= $1), 0) AS "price_sum!",
+      COUNT(*) FILTER (WHERE timestamp > $2 AND is_error) AS "recent_error_count!"
+    FROM tool_call_results
+    WHERE timestamp >= LEAST($1, $2)
+      AND NOT unknown_tool AND NOT unknown_batch
+      AND ($3::TEXT[] IS NULL OR mcp_url = ANY($3))
+      AND ($4::TEXT IS NULL OR tool_name = $4)
+    GROUP BY tool_name, mcp_url
+    "#,
+    raw_from,
+    circuit_from,
+    mcp_urls.as_deref(),
+    params.tool_name
+  )
+  .fetch_all(&pool)
+  .await;
+
+  let rows = match rows {
+    Ok(rows) => rows,
+    Err(e) => {
+      error!(error = %e, "Failed to load tool statistics");
+      return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+  };
+
+  let mut open_circuits = HashSet::new();
+  let mut aggregates = Vec::with_capacity(rows.len());
+  for row in rows {
+    if row.recent_error_count >= N_ERROR_THRESHOLD {
+      open_circuits.insert((row.tool_name.clone(), row.mcp_url.clone()));
+    }
+    if row.call_count > 0 {
+      aggregates.push(ToolCallAggregate {
+        tool_name: row.tool_name,
+        mcp_url: row.mcp_url,
+        call_count: row.call_count,
+        error_count: row.error_count,
+        success_count: row.success_count,
+        latency_sum_ms: row.latency_sum_ms,
+        p50_latency_ms: row.p50_latency_ms,
+        p95_latency_ms: row.p95_latency_ms,
+        p99_latency_ms: row.p99_latency_ms,
+        last_called_at: row.last_called_at,
+        response_bytes_sum: row.response_bytes_sum,
+        response_bytes_count: row.response_bytes_count,
+        response_tokens_sum: row.response_tokens_sum,
+        response_tokens_count: row.response_tokens_count,
+        price_sum: row.price_sum,
+      });
+    }
+  }
+
+  if from < raw_from {
+    match rollup_aggregates(&pool, from, raw_from, retention, mcp_urls.as_deref(), params.tool_name.as_deref()).await {
+      Ok(rolled) => aggregates.extend(rolled),
+      Err(e) => {
+        error!(error = %e, "Failed to load tool call rollups");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+      }
+    }
+  }
+
+  let tools = merge_aggregates(aggregates)
+    .into_iter()
+    .map(|aggregate| ToolStats {
+      error_rate: aggregate.error_count as f64 / aggregate.call_count as f64,
+      mean_latency_ms: aggregate.mean_latency_ms(),
+      mean_response_bytes: aggregate.mean_response_bytes(),
+      mean_response_tokens: aggregate.mean_response_tokens(),
+      spend: aggregate.price_sum,
+      circuit_state: if open_circuits.contains(&(aggregate.tool_name.clone(), aggregate.mcp_url.clone())) {
+        CircuitState::Open
+      } else {
+        CircuitState::Closed
+      },
+      tool_name: aggregate.tool_name,
+      mcp_url: aggregate.mcp_url,
+      call_count: aggregate.call_count,
+      error_count: aggregate.error_count,
+      p50_latency_ms: aggregate.p50_latency_ms,
+      p95_latency_ms: aggregate.p95_latency_ms,
+      p99_latency_ms: aggregate.p99_latency_ms,
+      last_called_at: aggregate.last_called_at,
+    })
+    .collect();
+
+  (StatusCode::OK, Json(ToolStatsResponse { from, to, tools })).into_response()
+}
@@ -1,33 +1,54 @@
 use std::{
   collections::{HashMap, HashSet},
   sync::Arc,
-  time::{Duration, Instant},
+  time::Duration,
 };
 
-use qdrant_client::Qdrant;
+use chrono::{DateTime, TimeDelta, Utc};
+use metrics_exporter_prometheus::PrometheusHandle;
 use rmcp::{RoleClient, model::ClientInfo, service::RunningService};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 use tokio::sync::RwLock;
 
+use crate::{
+  batches::Batch,
+  health::{HealthState, ServerHealth},
+  log_writer::LogWriter,
+  ping_history::PingHistory,
+  reconnect::ReconnectState,
+  tool_metrics::LogValidation,
+  vector_store::VectorStore,
+};
+
 pub(crate) type BatchId = String;
-pub(crate) type RegistrationTime = Instant;
 
 pub(crate) type DynamicMcpClient = Arc<RunningService<RoleClient, ClientInfo>>;
 
 pub(crate) struct ServerStatus {
   pub(crate) client: DynamicMcpClient,
-  pub(crate) active_batches: HashMap<BatchId, RegistrationTime>,
+  pub(crate) active_batches: HashSet<BatchId>,
+  /// Names of the tools the server listed when it was last (re)connected.
+  pub(crate) tools: HashSet<String>,
   pub(crate) latency_history: Vec<Duration>,
+  pub(crate) health: ServerHealth,
+  pub(crate) reconnect: ReconnectState,
 }
 
 pub(crate) type ServerMap = HashMap<String, ServerStatus>;
 
 pub(crate) struct AppData {
   pub(crate) servers: ServerMap,
-  pub(crate) batch_map: HashMap<BatchId, HashSet<String>>,
-  pub(crate) qdrant: Arc<Qdrant>,
+  pub(crate) batch_map: HashMap<BatchId, Batch>,
+  pub(crate) vector_store: Arc<dyn VectorStore>,
+  pub(crate) embedding_model: String,
+  pub(crate) embedding_template: String,
   pub(crate) pool: PgPool,
+  pub(crate) metrics_handle: PrometheusHandle,
+  /// How long raw `tool_call_results` rows are kept before only their rollups remain.
+  pub(crate) tool_call_retention: TimeDelta,
+  pub(crate) log_writer: LogWriter,
+  pub(crate) log_validation: LogValidation,
 }
 
 pub(crate) type AppState = Arc<RwLock<AppData>>;
@@ -35,6 +56,11 @@ pub(crate) type AppState = Arc<RwLock<AppData>>;
 #[derive(Deserialize)]
 pub(crate) struct RegisterRequest {
   pub(crate) mcp_urls: Vec<String>,
+  /// Idle time after which the batch expires. Defaults to `TIMEOUT_DURATION`.
+  pub(crate) ttl_seconds: Option<u64>,
+  /// Most the batch may spend on priced tool calls, in the currency of `/prices`. Enforced
+  /// by budget-aware routing.
+  pub(crate) budget: Option<f64>,
 }
 
 #[derive(Serialize)]
@@ -42,6 +68,13 @@ pub(crate) struct RegisterResponse {
   pub(crate) message: String,
   pub(crate) registered_id: Option<String>,
   pub(crate) urls: Vec<String>,
+  pub(crate) ttl_seconds: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct RenewBatchResponse {
+  pub(crate) message: String,
+  pub(crate) expires_in_seconds: Option<u64>,
 }
 
 #[derive(Deserialize)]
@@ -59,6 +92,10 @@ pub(crate) struct UnregisterResponse {
 #[derive(Deserialize)]
 pub(crate) struct BatchMetricsRequest {
   pub(crate) mcp_urls: Vec<String>,
+  /// Adds the ping history between `from` and `to` (default now) to each result, including
+  /// for servers that are no longer monitored.
+  pub(crate) from: Option<DateTime<Utc>>,
+  pub(crate) to: Option<DateTime<Utc>>,
 }
 
 #[derive(Serialize)]
@@ -66,5 +103,27 @@ pub(crate) struct MetricResult {
   pub(crate) url: String,
   pub(crate) average_latency_ms: Option<f64>,
   pub(crate) sample_count: Option<usize>,
+  pub(crate) health: Option<HealthState>,
+  pub(crate) consecutive_failures: Option<u32>,
+  pub(crate) last_success_seconds_ago: Option<u64>,
+  pub(crate) failed_ping_count: Option<usize>,
+  pub(crate) last_failure_seconds_ago: Option<u64>,
+  pub(crate) last_ping_error: Option<String>,
+  pub(crate) history: Option<PingHistory>,
   pub(crate) error: Option<String>,
 }
+
+#[derive(Deserialize)]
+pub(crate) struct MigrateEmbeddingsRequest {
+  pub(crate) model: String,
+  pub(crate) template: Option<String>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct MigrateEmbeddingsResponse {
+  pub(crate) message: String,
+  pub(crate) previous_model: String,
+  pub(crate) model: String,
+  pub(crate) collection: Option<String>,
+  pub(crate) tools_migrated: usize,
+}
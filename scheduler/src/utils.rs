@@ -1,14 +1,30 @@
-use crate::types::{MetricResult, ServerStatus};
+use std::time::Duration;
 
+use crate::{
+  RTT_SAMPLE_COUNT,
+  types::{MetricResult, ServerStatus},
+};
+
+/// Averages successful pings only; failed pings are reported through the health fields.
 pub(crate) fn calculate_average_latency(url: String, status: &ServerStatus) -> MetricResult {
+  let health = &status.health;
+  let mut result = MetricResult {
+    url,
+    average_latency_ms: Some(0.0),
+    sample_count: Some(0),
+    health: Some(health.state()),
+    consecutive_failures: Some(health.consecutive_failures),
+    last_success_seconds_ago: health.last_success.map(|at| at.elapsed().as_secs()),
+    failed_ping_count: Some(health.failure_history.len()),
+    last_failure_seconds_ago: health.failure_history.last().map(|f| f.at.elapsed().as_secs()),
+    last_ping_error: health.failure_history.last().map(|f| f.error.clone()),
+    history: None,
+    error: None,
+  };
+
   let history_count = status.latency_history.len();
   if history_count == 0 {
-    return MetricResult {
-      url,
-      average_latency_ms: Some(0.0),
-      sample_count: Some(0),
-      error: None,
-    };
+    return result;
   }
 
   let total_nanos = status.latency_history.iter().map(|d| d.as_nanos() as f64).sum::<f64>();
@@ -16,10 +32,24 @@ pub(crate) fn calculate_average_latency(url: String, status: &ServerStatus) -> M
   let average_nanos = total_nanos / (history_count as f64);
   let average_ms = average_nanos / 1_000_000.0;
 
-  MetricResult {
-    url,
-    average_latency_ms: Some(average_ms),
-    sample_count: Some(history_count),
-    error: None,
+  result.average_latency_ms = Some(average_ms);
+  result.sample_count = Some(history_count);
+  result
+}
+
+/// How much the current transport round trip, the mean of the last `RTT_SAMPLE_COUNT`
+/// successful pings, exceeds the mean of all pings kept. Logged call latencies already include
+/// the usual round trip, so only the increase is news.
+pub(crate) fn rtt_increase_ms(status: &ServerStatus) -> Option<f64> {
+  let history = &status.latency_history;
+  let samples = &history[history.len().saturating_sub(RTT_SAMPLE_COUNT)..];
+  if samples.is_empty() {
+    return None;
   }
+
+  Some((mean_ms(samples) - mean_ms(history)).max(0.0))
+}
+
+fn mean_ms(samples: &[Duration]) -> f64 {
+  samples.iter().map(|d| d.as_secs_f64() * 1000.0).sum::<f64>() / samples.len() as f64
 }
@@ -0,0 +1,161 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::collections::{DESCRIPTION_VECTOR, NAME_VECTOR, SCHEMA_VECTOR};
+
+/// Payload stored with every tool point. All values are strings; structured fields such as
+/// `inputSchema` are stored as JSON text.
+pub(crate) type Payload = HashMap<String, String>;
+
+/// Named dense vectors of a tool, keyed by `NAME_VECTOR`, `DESCRIPTION_VECTOR` and `SCHEMA_VECTOR`.
+pub(crate) type ToolVectors = HashMap<String, Vec<f32>>;
+
+#[derive(Clone, Debug)]
+pub(crate) struct ToolPoint {
+  pub(crate) id: String,
+  pub(crate) vectors: ToolVectors,
+  pub(crate) payload: Payload,
+}
+
+impl ToolPoint {
+  pub(crate) fn payload_str(&self, key: &str) -> Option<String> {
+    self.payload.get(key).cloned()
+  }
+}
+
+/// Matches points whose payload `key` equals any of `any_of`.
+pub(crate) struct PayloadFilter {
+  pub(crate) key: String,
+  pub(crate) any_of: Vec<String>,
+}
+
+impl PayloadFilter {
+  pub(crate) fn matches(key: &str, any_of: impl IntoIterator<Item = String>) -> Self {
+    Self {
+      key: key.to_string(),
+      any_of: any_of.into_iter().collect(),
+    }
+  }
+
+  pub(crate) fn accepts(&self, payload: &Payload) -> bool {
+    payload.get(&self.key).is_some_and(|value| self.any_of.contains(value))
+  }
+}
+
+/// How much each named vector contributes when comparing two tools, or a tool and a query.
+#[derive(Clone, Copy)]
+pub(crate) struct VectorWeights {
+  pub(crate) name: f32,
+  pub(crate) description: f32,
+  pub(crate) schema: f32,
+}
+
+impl VectorWeights {
+  pub(crate) fn iter(&self) -> [(&'static str, f32); 3] {
+    [
+      (NAME_VECTOR, self.name),
+      (DESCRIPTION_VECTOR, self.description),
+      (SCHEMA_VECTOR, self.schema),
+    ]
+  }
+}
+
+/// Storage for tool embeddings.
+///
+/// Points live in collections, one per embedding model and dimension (see
+/// `collection_name_for`). `TOOL_COLLECTION_NAME` is an alias resolving to the collection
+/// currently in use; every method taking a collection name accepts it.
+#[async_trait]
+pub(crate) trait VectorStore: Send + Sync {
+  /// The collection `TOOL_COLLECTION_NAME` currently resolves to, if any.
+  async fn active_collection(&self) -> Result<Option<String>>;
+
+  /// The embedding model recorded on the active collection.
+  async fn active_embedding_model(&self) -> Result<Option<String>>;
+
+  /// Creates the collection for `model` if it does not exist yet and returns its name.
+  async fn create_collection(&self, model: &str, vector_size: u64) -> Result<String>;
+
+  /// Makes sure `TOOL_COLLECTION_NAME` resolves to a collection able to hold vectors of
+  /// `vector_size`, creating one for `model` if there is none. Fails if an embedding
+  /// migration is required first.
+  async fn ensure_collection(&self, model: &str, vector_size: u64) -> Result<()>;
+
+  /// Atomically points `TOOL_COLLECTION_NAME` at `collection`.
+  async fn activate_collection(&self, collection: &str) -> Result<()>;
+
+  async fn upsert(&self, collection: &str, points: Vec<ToolPoint>) -> Result<()>;
+
+  async fn delete(&self, collection: &str, ids: Vec<String>) -> Result<()>;
+
+  /// Returns every point matching `filter`, or all points if there is none.
+  async fn scroll(&self, collection: &str, filter: Option<&PayloadFilter>, with_vectors: bool) -> Result<Vec<ToolPoint>>;
+
+  /// Returns up to `limit` points matching `filter`, ranked by cosine similarity between
+  /// `query` and their `vector_name` vector.
+  async fn search(
+    &self,
+    collection: &str,
+    vector_name: &str,
+    query: &[f32],
+    filter: Option<&PayloadFilter>,
+    limit: usize,
+  ) -> Result<Vec<(ToolPoint, f32)>>;
+
+  /// Every ordered pair of ids among `points` whose `weighted_similarity` is at least
+  /// `threshold`. The default compares the vectors carried by `points`; backends able to
+  /// compare stored vectors in place override it.
+  async fn similar_pairs(
+    &self,
+    _collection: &str,
+    points: &[ToolPoint],
+    weights: VectorWeights,
+    threshold: f32,
+  ) -> Result<HashSet<(String, String)>> {
+    let mut pairs = HashSet::new();
+
+    for (i, a) in points.iter().enumerate() {
+      for b in &points[i + 1..] {
+        if weighted_similarity(&a.vectors, &b.vectors, weights) >= threshold {
+          pairs.insert((a.id.clone(), b.id.clone()));
+          pairs.insert((b.id.clone(), a.id.clone()));
+        }
+      }
+    }
+
+    Ok(pairs)
+  }
+}
+
+/// Weighted mean of the per-vector cosine similarities of two tools, over the vectors
+/// both of them have.
+pub(crate) fn weighted_similarity(a: &ToolVectors, b: &ToolVectors, weights: VectorWeights) -> f32 {
+  let mut total = 0.0;
+  let mut weight_sum = 0.0;
+
+  for (name, weight) in weights.iter() {
+    if weight <= 0.0 {
+      continue;
+    }
+    if let (Some(a), Some(b)) = (a.get(name), b.get(name)) {
+      total += weight * cosine_similarity(a, b);
+      weight_sum += weight;
+    }
+  }
+
+  if weight_sum == 0.0 { 0.0 } else { total / weight_sum }
+}
+
+pub(crate) fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+  let dot_product: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+  let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+  let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+  if norm_a == 0.0 || norm_b == 0.0 {
+    0.0
+  } else {
+    dot_product / (norm_a * norm_b)
+  }
+}